@@ -0,0 +1,257 @@
+//! Threshold alerts, evaluated on every `pc_stats` poll. A rule is raised once
+//! its metric has been past the threshold for the configured time and
+//! cleared when it is back past it by the hysteresis margin, so a value
+//! hovering at the threshold doesn't flap. The device shows one alert at a
+//! time: the most recently raised one that is still active.
+
+use std::time::{Duration, Instant};
+
+use crate::background::sanitize_hid_text;
+use crate::config::{AlertRule, Metric};
+use crate::nostd_types::{ALERT_NO_REGION, EventType, region_index};
+use crate::stats::system_stats::{self, Reading};
+use crate::types::HidEvent;
+
+/// The readings rules are checked against.
+pub struct Metrics<'a> {
+    pub cpu_percent: f32,
+    pub ram_percent: f32,
+    pub temperatures: &'a [Reading],
+    pub fans: &'a [Reading],
+}
+
+impl Metrics<'_> {
+    fn value(&self, rule: &AlertRule) -> Option<f32> {
+        let sensor = |readings: &[Reading]| {
+            let wanted = rule.sensor.as_ref()?;
+            system_stats::select(readings, Some(std::slice::from_ref(wanted)), 1)
+                .first()
+                .map(|r| r.value)
+        };
+        match rule.metric {
+            Metric::Cpu => Some(self.cpu_percent),
+            Metric::Ram => Some(self.ram_percent),
+            Metric::Temperature => sensor(self.temperatures),
+            Metric::Fan => sensor(self.fans),
+        }
+    }
+}
+
+/// The alert to show, sent as `EventType::RawString`. An empty message
+/// clears it.
+pub struct AlertMsg {
+    /// Index into `SCREEN_SPACES` of the region to flash.
+    pub region: Option<u8>,
+    pub message: String,
+}
+
+impl AlertMsg {
+    pub fn clear() -> Self {
+        AlertMsg {
+            region: None,
+            message: String::new(),
+        }
+    }
+}
+
+impl HidEvent for AlertMsg {
+    fn to_bytes(&self) -> Vec<u8> {
+        if self.message.is_empty() {
+            return Vec::new();
+        }
+        let mut bytes = vec![self.region.unwrap_or(ALERT_NO_REGION)];
+        bytes.extend_from_slice(sanitize_hid_text(&self.message).as_bytes());
+        bytes
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::RawString
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct RuleState {
+    /// When the value first went past the threshold, while it stays there.
+    breached_since: Option<Instant>,
+    /// When the alert was raised, while it is active.
+    raised_at: Option<Instant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    states: Vec<RuleState>,
+    shown: Option<usize>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let states = rules.iter().map(|_| RuleState::default()).collect();
+        Alerts {
+            rules,
+            states,
+            shown: None,
+        }
+    }
+
+    /// Check every rule against `metrics`. Returns the message to send when
+    /// the alert to show has changed.
+    pub fn update(&mut self, metrics: &Metrics, now: Instant) -> Option<AlertMsg> {
+        for (rule, state) in self.rules.iter().zip(&mut self.states) {
+            let value = metrics.value(rule);
+            let (breached, cleared) = match (value, rule.above, rule.below) {
+                (Some(v), Some(above), _) => (v > above, v <= above - rule.hysteresis),
+                (Some(v), _, Some(below)) => (v < below, v >= below + rule.hysteresis),
+                // The sensor has gone away
+                _ => (false, true),
+            };
+            if state.raised_at.is_some() {
+                if cleared {
+                    *state = RuleState::default();
+                }
+                continue;
+            }
+            if !breached {
+                state.breached_since = None;
+                continue;
+            }
+            let since = *state.breached_since.get_or_insert(now);
+            if now.duration_since(since) >= Duration::from_secs(rule.for_secs) {
+                state.raised_at = Some(now);
+            }
+        }
+
+        let shown = self
+            .states
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| Some((s.raised_at?, i)))
+            .max()
+            .map(|(_, i)| i);
+        if shown == self.shown {
+            return None;
+        }
+        self.shown = shown;
+        Some(match shown {
+            Some(i) => AlertMsg {
+                region: self.rules[i].region.as_deref().and_then(region_index),
+                message: self.rules[i].message(),
+            },
+            None => AlertMsg::clear(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu_rule(above: f32, for_secs: u64, hysteresis: f32, message: &str) -> AlertRule {
+        AlertRule {
+            metric: Metric::Cpu,
+            sensor: None,
+            above: Some(above),
+            below: None,
+            for_secs,
+            hysteresis,
+            message: Some(message.into()),
+            region: Some("cpu".into()),
+        }
+    }
+
+    fn cpu(percent: f32) -> Metrics<'static> {
+        Metrics {
+            cpu_percent: percent,
+            ram_percent: 50.0,
+            temperatures: &[],
+            fans: &[],
+        }
+    }
+
+    /// The message sent by an update, `Some("")` for a clear.
+    fn update(alerts: &mut Alerts, percent: f32, at: Instant) -> Option<String> {
+        alerts.update(&cpu(percent), at).map(|msg| msg.message)
+    }
+
+    fn secs(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn raised_after_for_secs() {
+        let start = Instant::now();
+        let mut alerts = Alerts::new(vec![cpu_rule(90.0, 10, 0.0, "busy")]);
+        assert_eq!(update(&mut alerts, 95.0, start), None);
+        assert_eq!(update(&mut alerts, 95.0, secs(start, 9)), None);
+        // Dipping below the threshold starts the wait again
+        assert_eq!(update(&mut alerts, 85.0, secs(start, 10)), None);
+        assert_eq!(update(&mut alerts, 95.0, secs(start, 11)), None);
+        assert_eq!(update(&mut alerts, 95.0, secs(start, 20)), None);
+        assert_eq!(
+            update(&mut alerts, 95.0, secs(start, 21)),
+            Some("busy".into())
+        );
+        // Only changes are sent
+        assert_eq!(update(&mut alerts, 99.0, secs(start, 22)), None);
+    }
+
+    #[test]
+    fn cleared_past_the_hysteresis() {
+        let start = Instant::now();
+        let mut alerts = Alerts::new(vec![cpu_rule(90.0, 0, 5.0, "busy")]);
+        assert_eq!(update(&mut alerts, 95.0, start), Some("busy".into()));
+        assert_eq!(update(&mut alerts, 89.0, secs(start, 1)), None);
+        assert_eq!(update(&mut alerts, 85.1, secs(start, 2)), None);
+        assert_eq!(update(&mut alerts, 85.0, secs(start, 3)), Some("".into()));
+        assert_eq!(update(&mut alerts, 85.0, secs(start, 4)), None);
+    }
+
+    #[test]
+    fn payloads() {
+        let start = Instant::now();
+        let mut alerts = Alerts::new(vec![cpu_rule(90.0, 0, 0.0, "busy")]);
+        let raised = alerts.update(&cpu(95.0), start).unwrap();
+        assert_eq!(raised.event_type(), EventType::RawString);
+        assert_eq!(
+            raised.to_bytes(),
+            [&[region_index("cpu").unwrap()], &b"busy"[..]].concat()
+        );
+
+        let cleared = alerts.update(&cpu(50.0), secs(start, 1)).unwrap();
+        assert_eq!(cleared.event_type(), EventType::RawString);
+        assert_eq!(cleared.region, None);
+        assert_eq!(cleared.to_bytes(), Vec::<u8>::new());
+
+        let mut rule = cpu_rule(90.0, 0, 0.0, "busy");
+        rule.region = None;
+        let mut alerts = Alerts::new(vec![rule]);
+        let raised = alerts.update(&cpu(95.0), start).unwrap();
+        assert_eq!(raised.to_bytes()[0], ALERT_NO_REGION);
+    }
+
+    #[test]
+    fn newest_raised_alert_is_shown() {
+        let start = Instant::now();
+        let mut alerts = Alerts::new(vec![
+            cpu_rule(95.0, 0, 0.0, "very busy"),
+            cpu_rule(80.0, 0, 0.0, "busy"),
+        ]);
+        assert_eq!(update(&mut alerts, 85.0, start), Some("busy".into()));
+        assert_eq!(
+            update(&mut alerts, 99.0, secs(start, 1)),
+            Some("very busy".into())
+        );
+        // The older alert is shown again once the newer one clears
+        assert_eq!(
+            update(&mut alerts, 90.0, secs(start, 2)),
+            Some("busy".into())
+        );
+        assert_eq!(update(&mut alerts, 70.0, secs(start, 3)), Some("".into()));
+
+        // Raised on the same poll, the later rule wins
+        assert_eq!(
+            update(&mut alerts, 99.0, secs(start, 4)),
+            Some("busy".into())
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! On-disk cache of album art, already thumbnailed and QGF-encoded, so a
+//! track that comes round again doesn't need its art downloaded or decoded.
+//!
+//! Art is thumbnailed once for each distinct `album_art` region size. Each
+//! entry is one file, named after a hash of its key and the thumbnail size.
+//! The file starts with the key itself, so two keys that share a file name
+//! read as a miss rather than the wrong cover. Art known only by its bytes
+//! is keyed by their 64-bit hash and length, which two different images are
+//! vanishingly unlikely to share. Reading an entry bumps its modification
+//! time, and the oldest entries are deleted when the cache is over its limit.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::background::qgf_art::{self, Thumbnails};
+use crate::config::ArtCacheConfig;
+use crate::nostd_types::content_hash;
+
+const EXTENSION: &str = "qgf";
+
+#[derive(Debug, Clone)]
+pub struct ArtCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Thumbnail bounds, one per region size. Part of every key, so art
+    /// encoded for another layout isn't reused.
+    sizes: Vec<(u32, u32)>,
+}
+
+impl ArtCache {
+    pub fn new(config: &ArtCacheConfig, sizes: Vec<(u32, u32)>) -> Self {
+        ArtCache {
+            dir: config.dir.clone(),
+            max_bytes: config.max_mb * 1024 * 1024,
+            sizes,
+        }
+    }
+
+    /// Whether any device has somewhere to draw art.
+    pub fn wanted(&self) -> bool {
+        !self.sizes.is_empty()
+    }
+
+    /// The cache key for an image known only by its bytes.
+    pub fn content_key(image: &[u8]) -> String {
+        format!("fnv64:{:016x}:{}", fnv64(image), image.len())
+    }
+
+    fn path(&self, key: &str, (w, h): (u32, u32)) -> PathBuf {
+        self.dir.join(format!(
+            "{:08x}-{w}x{h}.{EXTENSION}",
+            content_hash(key.as_bytes())
+        ))
+    }
+
+    /// The art stored under `key`, if it is there at every size.
+    pub fn get(&self, key: &str) -> Option<Thumbnails> {
+        self.sizes
+            .iter()
+            .map(|&size| Some((size, self.read(key, size)?)))
+            .collect()
+    }
+
+    fn read(&self, key: &str, size: (u32, u32)) -> Option<Vec<u8>> {
+        let path = self.path(key, size);
+        let mut data = fs::read(&path).ok()?;
+        let split = data.iter().position(|b| *b == b'\n')?;
+        if &data[..split] != key.as_bytes() {
+            return None;
+        }
+        // Mark it as recently used, so eviction keeps it
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(data.split_off(split + 1))
+    }
+
+    /// Store `thumbnails` under `key`, then trim the cache back to its
+    /// limit.
+    pub fn put(&self, key: &str, thumbnails: &Thumbnails) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        for (size, qgf) in thumbnails {
+            self.write(key, *size, qgf)?;
+        }
+        self.evict()
+    }
+
+    fn write(&self, key: &str, size: (u32, u32), qgf: &[u8]) -> io::Result<()> {
+        let path = self.path(key, size);
+        let mut data = Vec::with_capacity(key.len() + 1 + qgf.len());
+        data.extend_from_slice(key.as_bytes());
+        data.push(b'\n');
+        data.extend_from_slice(qgf);
+        // Write then rename, so a reader never sees half an entry
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// The art stored under `key`, or else `image` thumbnailed and encoded
+    /// at every size and stored there. Runs on the blocking pool, file
+    /// access included.
+    pub async fn get_or_encode(&self, key: String, image: Vec<u8>) -> Option<Thumbnails> {
+        let cache = self.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Some(thumbnails) = cache.get(&key) {
+                return Some(thumbnails);
+            }
+            let thumbnails = qgf_art::thumbnails_to_qgf(&image, &cache.sizes)?;
+            if let Err(e) = cache.put(&key, &thumbnails) {
+                eprintln!("Could not cache album art: {e}");
+            }
+            Some(thumbnails)
+        })
+        .await
+        .ok()?
+    }
+
+    /// Delete the least recently used entries until the cache fits.
+    fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_none_or(|e| e != EXTENSION) {
+                continue;
+            }
+            // Another task may be evicting at the same time
+            let meta = match entry.metadata() {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                meta => meta?,
+            };
+            entries.push((meta.modified()?, meta.len(), path));
+        }
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                result => result?,
+            }
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+/// 64-bit FNV-1a over `data`. `content_hash` is only 32 bits, too few to
+/// tell every image in a cache apart.
+fn fnv64(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A cache in a directory of its own for each test.
+    fn cache(name: &str, sizes: Vec<(u32, u32)>, max_bytes: u64) -> ArtCache {
+        let dir = std::env::temp_dir().join(format!(
+            "slipstream-art-cache-{}-{name}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        ArtCache {
+            dir,
+            max_bytes,
+            sizes,
+        }
+    }
+
+    fn thumbnails(cache: &ArtCache, fill: u8) -> Thumbnails {
+        cache
+            .sizes
+            .iter()
+            .map(|&size| (size, vec![fill; 100]))
+            .collect()
+    }
+
+    fn entries(cache: &ArtCache) -> usize {
+        fs::read_dir(&cache.dir).unwrap().count()
+    }
+
+    #[test]
+    fn get_and_put() {
+        let cache = cache("get-put", vec![(50, 50), (80, 60)], 1 << 20);
+        assert_eq!(cache.get("a"), None);
+        cache.put("a", &thumbnails(&cache, 1)).unwrap();
+        cache.put("b", &thumbnails(&cache, 2)).unwrap();
+        assert_eq!(cache.get("a"), Some(thumbnails(&cache, 1)));
+        assert_eq!(cache.get("b"), Some(thumbnails(&cache, 2)));
+        assert_eq!(entries(&cache), 4);
+
+        // Art is only there if it is there at every size
+        fs::remove_file(cache.path("a", (80, 60))).unwrap();
+        assert_eq!(cache.get("a"), None);
+        // Nor is art encoded for another layout
+        let resized = ArtCache {
+            sizes: vec![(50, 50), (100, 100)],
+            ..cache.clone()
+        };
+        assert_eq!(resized.get("b"), None);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn key_is_checked_on_read() {
+        let cache = cache("collision", vec![(50, 50)], 1 << 20);
+        cache.put("a", &thumbnails(&cache, 1)).unwrap();
+        // As if "b" hashed to the same file name as "a"
+        fs::rename(cache.path("a", (50, 50)), cache.path("b", (50, 50))).unwrap();
+        assert_eq!(cache.get("b"), None);
+        // Nor is a file without the key line taken for art
+        fs::write(cache.path("c", (50, 50)), [7; 100]).unwrap();
+        assert_eq!(cache.get("c"), None);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        // Each entry is the one-letter key, a newline and 100 bytes
+        let cache = cache("evict", vec![(50, 50)], 3 * 102);
+        let start = SystemTime::now() - Duration::from_secs(300);
+        for (i, key) in ["a", "b", "c"].into_iter().enumerate() {
+            cache.put(key, &thumbnails(&cache, 1)).unwrap();
+            let file = fs::File::options()
+                .write(true)
+                .open(cache.path(key, (50, 50)))
+                .unwrap();
+            file.set_modified(start + Duration::from_secs(60 * i as u64))
+                .unwrap();
+        }
+        // Reading "a" makes "b" the least recently used
+        assert!(cache.get("a").is_some());
+        // Files that aren't entries don't count
+        fs::write(cache.dir.join("notes.txt"), [0; 1000]).unwrap();
+        cache.put("d", &thumbnails(&cache, 1)).unwrap();
+        assert_eq!(cache.get("b"), None);
+        for key in ["a", "c", "d"] {
+            assert!(cache.get(key).is_some(), "{key}");
+        }
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_eviction() {
+        let cache = cache("concurrent", vec![(50, 50)], 1 << 20);
+        for i in 0..200 {
+            cache.put(&i.to_string(), &thumbnails(&cache, 1)).unwrap();
+        }
+        // Every evictor finds files the others have already deleted
+        let cache = ArtCache {
+            max_bytes: 0,
+            ..cache
+        };
+        std::thread::scope(|s| {
+            let evictors: Vec<_> = (0..4).map(|_| s.spawn(|| cache.evict())).collect();
+            for evictor in evictors {
+                evictor.join().unwrap().unwrap();
+            }
+        });
+        assert_eq!(entries(&cache), 0);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_or_encode_prefers_the_cache() {
+        let cache = cache("get-or-encode", vec![(50, 50)], 1 << 20);
+        cache.put("a", &thumbnails(&cache, 1)).unwrap();
+        // Not an image, so only the cache can answer
+        let image = b"not an image".to_vec();
+        assert_eq!(
+            cache.get_or_encode("a".into(), image.clone()).await,
+            Some(thumbnails(&cache, 1))
+        );
+        assert_eq!(cache.get_or_encode("b".into(), image).await, None);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+//! Recording and replaying the reports sent to a device. A capture is a text
+//! file with one report per line: microseconds since the capture started,
+//! then the 32 report bytes in hex. Lines starting with '#' are comments.
+
+use crate::background::transport::{Transport, TransportError};
+use crate::nostd_types::{HidEventImpl, MAX_HID_EVENT_SIZE};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl CaptureWriter {
+    /// Create (or truncate) the capture file at `path`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# slipstream capture: <micros> <report hex>")?;
+        Ok(CaptureWriter {
+            out,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, report: &HidEventImpl) -> io::Result<()> {
+        let micros = self.start.elapsed().as_micros();
+        let hex: String = report.iter().map(|b| format!("{b:02x}")).collect();
+        writeln!(self.out, "{micros} {hex}")?;
+        // Flush every report so the capture survives a crash, which is when
+        // it is most wanted.
+        self.out.flush()
+    }
+}
+
+fn invalid(line: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("capture line {line}: {msg}"),
+    )
+}
+
+/// Read a capture written by `CaptureWriter`.
+pub fn read_capture(path: &Path) -> io::Result<Vec<(Duration, HidEventImpl)>> {
+    let mut reports = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (micros, hex) = line
+            .split_once(' ')
+            .ok_or_else(|| invalid(i + 1, "expected <micros> <hex>"))?;
+        let micros: u64 = micros
+            .parse()
+            .map_err(|_| invalid(i + 1, "bad timestamp"))?;
+        if hex.len() != MAX_HID_EVENT_SIZE * 2 {
+            return Err(invalid(i + 1, "report is not 32 bytes"));
+        }
+        // Byte offsets below must fall on characters
+        if !hex.is_ascii() {
+            return Err(invalid(i + 1, "bad hex"));
+        }
+        let mut report = [0u8; MAX_HID_EVENT_SIZE];
+        for (j, b) in report.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[j * 2..j * 2 + 2], 16)
+                .map_err(|_| invalid(i + 1, "bad hex"))?;
+        }
+        reports.push((Duration::from_micros(micros), report));
+    }
+    Ok(reports)
+}
+
+/// Write a capture to `transport`, keeping the original gaps between reports
+/// divided by `speed` (2.0 replays twice as fast). The transport must
+/// already be open.
+pub async fn replay(
+    transport: &mut dyn Transport,
+    capture: &[(Duration, HidEventImpl)],
+    speed: f64,
+) -> Result<(), TransportError> {
+    let start = tokio::time::Instant::now();
+    for (at, report) in capture {
+        tokio::time::sleep_until(start + at.div_f64(speed)).await;
+        transport.write_report(report)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::transport::MemoryTransport;
+    use std::path::PathBuf;
+
+    /// A capture file path of its own for each test.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "slipstream-capture-{}-{name}.txt",
+            std::process::id()
+        ))
+    }
+
+    fn report(first: u8) -> HidEventImpl {
+        let mut report = [0u8; MAX_HID_EVENT_SIZE];
+        report[0] = first;
+        report[MAX_HID_EVENT_SIZE - 1] = 0xAB;
+        report
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round-trip");
+        let mut writer = CaptureWriter::create(&path).unwrap();
+        for first in [0xFA, 0x00, 0xAF] {
+            writer.record(&report(first)).unwrap();
+        }
+        drop(writer);
+
+        let capture = read_capture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let reports: Vec<HidEventImpl> = capture.iter().map(|(_, r)| *r).collect();
+        assert_eq!(reports, [report(0xFA), report(0x00), report(0xAF)]);
+        assert!(capture.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let path = temp_path("malformed");
+        let hex = "00".repeat(MAX_HID_EVENT_SIZE);
+        let cases = [
+            (format!("12{hex}"), "expected <micros> <hex>"),
+            (format!("soon {hex}"), "bad timestamp"),
+            ("12 00ff".to_string(), "report is not 32 bytes"),
+            (format!("12 zz{}", &hex[2..]), "bad hex"),
+            (format!("12 \u{e9}{}", &hex[2..]), "bad hex"),
+        ];
+        for (line, error) in cases {
+            std::fs::write(&path, format!("# comment\n\n1 {hex}\n{line}\n")).unwrap();
+            let e = read_capture(&path).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(e.to_string(), format!("capture line 4: {error}"));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_keeps_the_gaps() {
+        let mut transport = MemoryTransport::new();
+        assert!(transport.reopen());
+        let capture = [
+            (Duration::ZERO, report(1)),
+            (Duration::from_millis(40), report(2)),
+            (Duration::from_millis(80), report(3)),
+        ];
+        let start = Instant::now();
+        replay(&mut transport, &capture, 2.0).await.unwrap();
+        // Twice as fast: 80ms of capture takes 40ms
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(40), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(80), "{elapsed:?}");
+        assert_eq!(transport.reports(), [report(1), report(2), report(3)]);
+    }
+}
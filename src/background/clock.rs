@@ -1,6 +1,6 @@
 use crate::nostd_types::EventType;
-use crate::nostd_types::{FOOTER, HEADER};
 use crate::types::HidEvent;
+use chrono::{Datelike, Timelike};
 
 pub struct Time {
     pub hours: u8,
@@ -11,6 +11,21 @@ pub struct Time {
     pub day: u8,
 }
 
+impl Time {
+    /// The current local time, in the layout ChibiOS' RTC expects.
+    pub fn now() -> Self {
+        let now = chrono::offset::Local::now();
+        Time {
+            hours: now.hour() as u8,
+            minutes: now.minute() as u8,
+            seconds: now.second() as u8,
+            year: (now.year() - 1900) as u8,
+            month: (now.month() - 1u32) as u8, // this is actually zero indexed: https://github.com/ChibiOS/ChibiOS/blob/259505e28665781f23323020174302cfa73fd48d/os/hal/src/hal_rtc.c#L265
+            day: now.day() as u8,
+        }
+    }
+}
+
 impl HidEvent for Time {
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
@@ -29,19 +44,6 @@ impl HidEvent for Time {
         bytes
     }
 
-    fn chunks(&self) -> Vec<Vec<u8>> {
-        let mut v = Vec::new();
-        let mut header = Vec::new();
-        header.extend_from_slice(&HEADER);
-        header.extend_from_slice(&[EventType::Clock as u8]);
-        v.push(header);
-
-        v.push(self.to_bytes());
-        let mut footer = Vec::new();
-        footer.extend_from_slice(&FOOTER);
-        v.push(footer);
-        v
-    }
     fn event_type(&self) -> EventType {
         EventType::Clock
     }
@@ -0,0 +1,132 @@
+use crate::background::media_control::{self, MediaAction};
+use crate::background::ts6::Ts6Command;
+use crate::nostd_types::DeviceCommand;
+use tokio::sync::mpsc;
+
+/// Routes commands sent by the keyboard to the background module that acts
+/// on them. `RequestRefresh` is answered by the HID handler itself.
+pub struct CommandRouter {
+    ts6: mpsc::Sender<Ts6Command>,
+    /// Performs media actions; `media_control::spawn` outside tests.
+    media: Box<dyn Fn(MediaAction) + Send + Sync>,
+}
+
+impl CommandRouter {
+    pub fn new(ts6: mpsc::Sender<Ts6Command>) -> Self {
+        Self::with_media(ts6, media_control::spawn)
+    }
+
+    /// A router that hands media actions to `media` instead of the system's
+    /// media player.
+    pub fn with_media(
+        ts6: mpsc::Sender<Ts6Command>,
+        media: impl Fn(MediaAction) + Send + Sync + 'static,
+    ) -> Self {
+        CommandRouter {
+            ts6,
+            media: Box::new(media),
+        }
+    }
+
+    pub fn dispatch(&self, cmd: DeviceCommand) {
+        match cmd {
+            DeviceCommand::MediaPlayPause
+            | DeviceCommand::MediaNext
+            | DeviceCommand::MediaPrevious => {
+                if let Some(action) = MediaAction::from_command(cmd) {
+                    (self.media)(action);
+                }
+            }
+            DeviceCommand::Ts6ToggleMute => {
+                // Don't queue up presses while TeamSpeak isn't connected
+                if let Err(e) = self.ts6.try_send(Ts6Command::ToggleMute) {
+                    eprintln!("Dropping TS6 command: {e}");
+                }
+            }
+            DeviceCommand::RequestRefresh | DeviceCommand::Capabilities | DeviceCommand::None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::hid::HidHandler;
+    use crate::background::transport::MemoryTransport;
+    use crate::config::DeviceConfig;
+    use std::sync::mpsc as std_mpsc;
+
+    /// A router whose media actions and TS6 commands land in the returned
+    /// receivers, with room for `ts6_capacity` TS6 commands.
+    fn router(
+        ts6_capacity: usize,
+    ) -> (
+        CommandRouter,
+        std_mpsc::Receiver<MediaAction>,
+        mpsc::Receiver<Ts6Command>,
+    ) {
+        let (media_tx, media_rx) = std_mpsc::channel();
+        let (ts6_tx, ts6_rx) = mpsc::channel(ts6_capacity);
+        let router = CommandRouter::with_media(ts6_tx, move |action| {
+            media_tx.send(action).unwrap();
+        });
+        (router, media_rx, ts6_rx)
+    }
+
+    /// Feed `commands` to a device as keyboard reports and dispatch what the
+    /// handler reads back.
+    async fn press(router: &CommandRouter, commands: &[DeviceCommand]) {
+        let transport = MemoryTransport::new();
+        let config: DeviceConfig = toml::from_str("vid = 0xfeed\npid = 0x0001").unwrap();
+        let mut handler = HidHandler::with_transport(&config, Box::new(transport.clone())).await;
+        for cmd in commands {
+            transport.push_input(cmd.to_report(&[]));
+        }
+        for cmd in handler.read_commands() {
+            router.dispatch(cmd);
+        }
+    }
+
+    #[tokio::test]
+    async fn media_keys_reach_the_player() {
+        let (router, media, _ts6) = router(1);
+        press(
+            &router,
+            &[
+                DeviceCommand::MediaPlayPause,
+                DeviceCommand::MediaNext,
+                DeviceCommand::MediaPrevious,
+            ],
+        )
+        .await;
+        assert_eq!(
+            media.try_iter().collect::<Vec<_>>(),
+            [
+                MediaAction::PlayPause,
+                MediaAction::Next,
+                MediaAction::Previous
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn mute_reaches_teamspeak() {
+        let (router, media, mut ts6) = router(1);
+        press(&router, &[DeviceCommand::Ts6ToggleMute]).await;
+        assert!(matches!(ts6.try_recv(), Ok(Ts6Command::ToggleMute)));
+        assert!(media.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn presses_are_dropped_while_teamspeak_is_behind() {
+        let (router, _media, mut ts6) = router(1);
+        press(
+            &router,
+            &[DeviceCommand::Ts6ToggleMute, DeviceCommand::Ts6ToggleMute],
+        )
+        .await;
+        // The second press found the channel full
+        assert!(matches!(ts6.try_recv(), Ok(Ts6Command::ToggleMute)));
+        assert!(ts6.try_recv().is_err());
+    }
+}
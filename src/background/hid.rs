@@ -1,94 +1,441 @@
+use crate::background::capture::CaptureWriter;
 use crate::background::clock;
+use crate::background::commands::CommandRouter;
+use crate::background::queue::{Coalesce, EventQueue};
+use crate::background::transport::{EmulatorTransport, HidapiTransport, Transport};
+use crate::config::DeviceConfig;
 use crate::nostd_types::*;
 use crate::types::*;
-use chrono::{Datelike, Timelike};
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-pub struct HidHandler {
-    device: hidapi::HidDevice,
-    vid: u16,
-    pid: u16,
+/// How often a device task checks for commands from the keyboard when no
+/// events are being published.
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often to look for an unplugged device coming back. hidapi has no
+/// hotplug notifications, so this is polled.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+/// How long to wait for the firmware to answer a Hello before assuming it
+/// predates the handshake.
+const HELLO_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The event types each connected device can show and subscribes to, so
+/// producers can skip work no device wants (e.g. encoding album art).
+#[derive(Default)]
+pub struct SupportedEvents {
+    /// Keyed by the device's index in `[[devices]]`; labels needn't be
+    /// unique.
+    devices: Mutex<HashMap<usize, u32>>,
 }
-fn new_device(vid: u16, pid: u16) -> Option<hidapi::HidDevice> {
-    let api = match hidapi::HidApi::new() {
-        Ok(api) => api,
-        Err(e) => {
-            eprintln!("Failed to initialise hidapi: {e}");
-            return None;
-        }
-    };
-    let devices = api
-        .device_list()
-        .filter(|d| d.vendor_id() == vid && d.product_id() == pid);
-    for device in devices {
-        let Ok(device) = api.open_path(device.path()) else {
-            continue;
+
+impl SupportedEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn set(&self, device: usize, mask: Option<u32>) {
+        let mut devices = self.devices.lock().unwrap();
+        match mask {
+            Some(mask) => devices.insert(device, mask),
+            None => devices.remove(&device),
         };
-        if device.write(&[0]).is_ok() {
-            return Some(device);
-        }
     }
-    None
+
+    /// Whether any connected device wants `event_type`. True while no device
+    /// is connected, so the latest state is still there to replay when one
+    /// appears.
+    pub fn any(&self, event_type: EventType) -> bool {
+        let devices = self.devices.lock().unwrap();
+        devices.is_empty() || devices.values().any(|m| m & event_type.bit() != 0)
+    }
+}
+
+/// Tells the firmware the device's layout; the payload is whichever layout
+/// the device it's sent to uses.
+struct LayoutEvent;
+
+impl HidEvent for LayoutEvent {
+    fn to_bytes(&self) -> Vec<u8> {
+        DEFAULT_LAYOUT.to_bytes().to_vec()
+    }
+
+    fn to_bytes_for(&self, layout: &Layout) -> Vec<u8> {
+        layout.to_bytes().to_vec()
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::Layout
+    }
+}
+
+pub struct HidHandler {
+    transport: Box<dyn Transport>,
+    config: DeviceConfig,
+    /// When we last tried to open the device.
+    last_attempt: Option<Instant>,
+    /// Hash of the album art the device is currently showing.
+    album_art_hash: Option<u32>,
+    /// Hashes of the process icons already sent to the device.
+    sent_icons: HashSet<u32>,
+    /// Sequence id of the next frame.
+    seq: u8,
+    /// The most recent event of each type and coalesce key (so every
+    /// watched process, every user's talk status), replayed in wire order on
+    /// `refresh` and when the device is plugged back in.
+    latest: BTreeMap<(u8, Option<String>), Arc<dyn HidEvent>>,
+    /// Where every report written to the device is recorded, if anywhere.
+    capture: Option<CaptureWriter>,
+    /// What the firmware said it supports; None until the handshake is done.
+    capabilities: Option<Capabilities>,
+    /// Commands read while waiting for the handshake, handled by `run`.
+    pending_commands: Vec<DeviceCommand>,
+    /// Where to report what this device wants, and its index there.
+    support: Option<(Arc<SupportedEvents>, usize)>,
 }
 
 impl HidHandler {
-    pub async fn new(vid: u16, pid: u16) -> Option<Self> {
-        let mut h = new_device(vid, pid).map(|device| HidHandler { device, vid, pid })?;
-        let now = chrono::offset::Local::now();
+    /// Create a handler for the device described by `config`. If it isn't
+    /// plugged in yet, `run` keeps looking for it. Devices with `emulate` set
+    /// render to a PNG instead.
+    pub async fn new(config: &DeviceConfig) -> Self {
+        let transport: Box<dyn Transport> = match &config.emulate {
+            Some(path) => Box::new(EmulatorTransport::new(path, config.resolved_layout)),
+            None => Box::new(HidapiTransport::new(config)),
+        };
+        Self::with_transport(config, transport).await
+    }
+
+    /// Create a handler that talks to the device through `transport`, e.g. a
+    /// `MemoryTransport` in tests.
+    pub async fn with_transport(config: &DeviceConfig, transport: Box<dyn Transport>) -> Self {
+        let mut h = HidHandler {
+            transport,
+            config: config.clone(),
+            last_attempt: None,
+            album_art_hash: None,
+            sent_icons: HashSet::new(),
+            seq: 0,
+            latest: BTreeMap::new(),
+            capture: None,
+            capabilities: None,
+            pending_commands: Vec::new(),
+            support: None,
+        };
+        if let Some(path) = &config.capture
+            && let Err(e) = h.capture_to(Path::new(path))
+        {
+            eprintln!("Could not create capture file {path}: {e}");
+        }
+        h.try_connect().await;
+        h
+    }
+
+    /// Record every report written to the device from now on to `path`,
+    /// for `slipstream replay`.
+    pub fn capture_to(&mut self, path: &Path) -> io::Result<()> {
+        self.capture = Some(CaptureWriter::create(path)?);
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.transport.is_open()
+    }
+
+    /// What the connected firmware supports, once it has been asked.
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.capabilities
+    }
+
+    /// Try to open the device and, if that works, find out what it supports
+    /// and bring it up to date with the current time and the latest state.
+    async fn try_connect(&mut self) {
+        self.last_attempt = Some(Instant::now());
+        if !self.transport.reopen() {
+            return;
+        }
+        println!("HID device {} connected", self.config.label());
+        self.handshake().await;
+        if self.is_connected() {
+            self.refresh().await;
+        }
+    }
+
+    /// Announce our protocol version and wait for the firmware to say what
+    /// it supports. Firmware that doesn't answer gets `Capabilities::LEGACY`.
+    async fn handshake(&mut self) {
+        self.capabilities = None;
+        let seq = self.next_seq();
+        for chunk in frame(EventType::Hello, seq, &[PROTOCOL_VERSION]) {
+            if !self.send_to_hid_device(&chunk) {
+                self.disconnect();
+                return;
+            }
+        }
+        let deadline = Instant::now() + HELLO_TIMEOUT;
+        while self.capabilities.is_none() && Instant::now() < deadline {
+            let commands = self.read_commands();
+            self.pending_commands.extend(commands);
+            if !self.is_connected() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        if self.capabilities.is_none() {
+            println!(
+                "HID device {} did not answer hello, assuming legacy firmware",
+                self.config.label()
+            );
+            self.set_capabilities(Capabilities::LEGACY);
+        }
+    }
+
+    fn set_capabilities(&mut self, caps: Capabilities) {
+        println!(
+            "HID device {}: protocol v{}, {}x{} screen, {} byte reports",
+            self.config.label(),
+            caps.version,
+            caps.width,
+            caps.height,
+            caps.max_report_size
+        );
+        if caps.version > PROTOCOL_VERSION {
+            eprintln!(
+                "Warning: device {} speaks protocol v{}, newer than our v{PROTOCOL_VERSION}",
+                self.config.label(),
+                caps.version
+            );
+        }
+        if (caps.max_report_size as usize) < MAX_HID_EVENT_SIZE {
+            eprintln!(
+                "Warning: device {} takes {} byte reports, but we send {MAX_HID_EVENT_SIZE}",
+                self.config.label(),
+                caps.max_report_size
+            );
+        }
+        let unsupported: Vec<&str> = EventType::ALL
+            .iter()
+            .filter(|t| **t != EventType::None && **t != EventType::Hello)
+            .filter(|t| self.config.subscribes_to(**t) && !caps.supports(**t))
+            .map(|t| t.name())
+            .collect();
+        if !unsupported.is_empty() {
+            println!(
+                "HID device {} does not support {:?}, not sending them",
+                self.config.label(),
+                unsupported
+            );
+        }
+        self.capabilities = Some(caps);
+        self.update_support();
+    }
+
+    /// Tell producers what this device wants now.
+    fn update_support(&self) {
+        let Some((support, device)) = &self.support else {
+            return;
+        };
+        let mask = self
+            .capabilities
+            .filter(|_| self.is_connected())
+            .map(|caps| {
+                EventType::ALL
+                    .iter()
+                    .filter(|t| caps.supports(**t) && self.config.subscribes_to(**t))
+                    .fold(0, |mask, t| mask | t.bit())
+            });
+        support.set(*device, mask);
+    }
+
+    fn next_seq(&mut self) -> u8 {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        seq
+    }
+
+    fn disconnect(&mut self) {
+        eprintln!(
+            "HID device {} is gone, waiting for it to come back",
+            self.config.label()
+        );
+        self.transport.close();
+        self.album_art_hash = None;
+        self.sent_icons.clear();
+        self.capabilities = None;
+        self.update_support();
+    }
+
+    /// Resend the layout, the current time and the latest state of every
+    /// other type, e.g. on reconnect or when the keyboard asks for a refresh
+    /// after a firmware reset.
+    pub async fn refresh(&mut self) {
+        // The device may have lost its image caches too.
+        self.album_art_hash = None;
+        self.sent_icons.clear();
+        self.publish_hid_event(Arc::new(LayoutEvent)).await;
+        let now = clock::Time::now();
         println!(
-            "Publishing initial clock event: {:?}:{:?}:{:?} {:?}:{:?}:{:?}",
-            now.hour(),
-            now.minute(),
-            now.second(),
-            now.year(),
-            now.month(),
-            now.day()
+            "Publishing clock event: {:?}:{:?}:{:?} {:?}:{:?}:{:?}",
+            now.hours,
+            now.minutes,
+            now.seconds,
+            now.year as u16 + 1900,
+            now.month + 1,
+            now.day
         );
-        h.publish_hid_event(Arc::new(clock::Time {
-            hours: now.hour() as u8,
-            minutes: now.minute() as u8,
-            seconds: now.second() as u8,
-            year: (now.year() - 1900) as u8,
-            month: (now.month() - 1u32) as u8, // this is actually zero indexed: https://github.com/ChibiOS/ChibiOS/blob/259505e28665781f23323020174302cfa73fd48d/os/hal/src/hal_rtc.c#L265
-            day: now.day() as u8,
-        }))
-        .await;
-        Some(h)
-    }
-
-    /// Returns false when the device is gone and could not be re-opened.
-    pub async fn publish_hid_event(&mut self, event: Arc<dyn HidEvent>) -> bool {
-        if event.event_type() == EventType::MediaUpdateShufflePlay {
-            return true;
-        }
-
-        for chunk in event.chunks() {
-            let mut c = [0 as u8; MAX_HID_EVENT_SIZE];
-            c[..chunk.len()].copy_from_slice(&chunk);
-            if !self.send_to_hid_device(&c) {
+        self.publish_hid_event(Arc::new(now)).await;
+        let replay: Vec<Arc<dyn HidEvent>> = self
+            .latest
+            .values()
+            .filter(|e| !matches!(e.event_type(), EventType::Clock | EventType::Layout))
+            .cloned()
+            .collect();
+        for event in replay {
+            if !self.is_connected() {
+                return;
+            }
+            self.publish_hid_event(event).await;
+        }
+    }
+
+    /// Drain any commands the keyboard has sent. Never blocks. Capabilities
+    /// replies are applied here rather than returned.
+    pub fn read_commands(&mut self) -> Vec<DeviceCommand> {
+        let mut commands = Vec::new();
+        if !self.is_connected() {
+            return commands;
+        }
+        loop {
+            match self.transport.read_report() {
+                Ok(None) => break,
+                Ok(Some(report)) => match DeviceCommand::parse(&report) {
+                    Some((DeviceCommand::Capabilities, args)) => match Capabilities::parse(args) {
+                        Some(caps) => self.set_capabilities(caps),
+                        None => eprintln!("Ignoring malformed capabilities report"),
+                    },
+                    Some((cmd, _args)) => commands.push(cmd),
+                    None => {}
+                },
+                Err(e) => {
+                    eprintln!("Error reading from device: {:?}", e);
+                    self.disconnect();
+                    break;
+                }
+            }
+        }
+        commands
+    }
+
+    /// Publish events to the device and act on the commands it sends back
+    /// until the daemon shuts down, re-opening the device whenever it is
+    /// unplugged and plugged back in. Each configured device runs its own
+    /// copy of this loop, so one device dropping out doesn't affect the
+    /// others. `support` is kept up to date with what this device wants,
+    /// under `device`, its index in `[[devices]]`.
+    pub async fn run(
+        mut self,
+        events: Arc<EventQueue>,
+        router: Arc<CommandRouter>,
+        support: Arc<SupportedEvents>,
+        device: usize,
+        shutting_down: Arc<AtomicBool>,
+    ) {
+        self.support = Some((support, device));
+        self.update_support();
+        loop {
+            if shutting_down.load(Ordering::Relaxed) {
+                return;
+            }
+            if !self.is_connected()
+                && self
+                    .last_attempt
+                    .is_none_or(|t| t.elapsed() >= RECONNECT_INTERVAL)
+            {
+                self.try_connect().await;
+            }
+            // Wake up regularly even when nothing is being published so
+            // commands from the keyboard are picked up promptly.
+            match tokio::time::timeout(COMMAND_POLL_INTERVAL, events.pop()).await {
+                Ok(Some(evt)) => self.publish_hid_event(evt).await,
+                Ok(None) => return,
+                Err(_) => {}
+            }
+            let mut commands = std::mem::take(&mut self.pending_commands);
+            commands.extend(self.read_commands());
+            for cmd in commands {
+                match cmd {
+                    DeviceCommand::RequestRefresh => self.refresh().await,
+                    cmd => router.dispatch(cmd),
+                }
+            }
+        }
+    }
+
+    /// Send an event to the device. While the device is unplugged the event
+    /// is only remembered, to be replayed when it comes back.
+    pub async fn publish_hid_event(&mut self, event: Arc<dyn HidEvent>) {
+        if !self.config.subscribes_to(event.event_type()) {
+            return;
+        }
+        let key = match event.coalesce() {
+            Coalesce::Type => Some(None),
+            Coalesce::Key(key) => Some(Some(key)),
+            Coalesce::Never => None,
+        };
+        if let Some(key) = key {
+            self.latest
+                .insert((event.event_type() as u8, key), event.clone());
+        }
+        if !self.is_connected()
+            || self
+                .capabilities
+                .is_some_and(|caps| !caps.supports(event.event_type()))
+        {
+            return;
+        }
+        if event.event_type() == EventType::AlbumArt {
+            if event.content_hash() == self.album_art_hash {
+                return;
+            }
+            self.album_art_hash = event.content_hash();
+        }
+        if event.event_type() == EventType::ProcessIcon
+            && let Some(hash) = event.content_hash()
+            && !self.sent_icons.insert(hash)
+        {
+            return;
+        }
+
+        let seq = self.next_seq();
+        for chunk in event.chunks(seq, &self.config.resolved_layout) {
+            if !self.send_to_hid_device(&chunk) {
                 // Device is gone and could not be re-opened; drop the rest of
                 // this frame rather than sending a torn event later.
-                return false;
+                self.disconnect();
+                return;
             }
         }
-        true
     }
-    fn send_to_hid_device(&mut self, chunk: &HidEventImpl) -> bool {
-        // handle report ID
-        let out: [u8; 33] = {
-            let mut new = [0u8; 33];
-            new[1..].copy_from_slice(chunk);
-            new
-        };
 
+    fn send_to_hid_device(&mut self, chunk: &HidEventImpl) -> bool {
         for _ in 0..3 {
-            match self.device.write(&out) {
-                Ok(_) => return true,
+            match self.transport.write_report(chunk) {
+                Ok(_) => {
+                    if let Some(capture) = &mut self.capture
+                        && let Err(e) = capture.record(chunk)
+                    {
+                        eprintln!("Error writing capture, stopping it: {e}");
+                        self.capture = None;
+                    }
+                    return true;
+                }
                 Err(e) => {
                     eprintln!("Error sending to device, trying to recreate: {:?}", e);
-                    match new_device(self.vid, self.pid) {
-                        Some(d) => self.device = d,
-                        None => return false,
+                    if !self.transport.reopen() {
+                        return false;
                     }
                 }
             }
@@ -96,3 +443,270 @@ impl HidHandler {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::now_playing::MediaInfo;
+    use crate::background::pc_stats::PCStatMsg;
+    use crate::background::process_watcher::Process;
+    use crate::background::transport::MemoryTransport;
+    use crate::background::ts6::Ts6HidEvent;
+
+    /// A report with `bytes` at the start, zero-padded.
+    fn report(bytes: &[u8]) -> HidEventImpl {
+        let mut report = [0u8; MAX_HID_EVENT_SIZE];
+        report[..bytes.len()].copy_from_slice(bytes);
+        report
+    }
+
+    const FOOTER_REPORT: [u8; 4] = [0xAF, 0x00, 0x0F, 0x00];
+
+    /// A connected handler that has finished its handshake and refresh, and
+    /// the transport to inspect what it writes.
+    async fn connected() -> (HidHandler, MemoryTransport) {
+        let transport = MemoryTransport::new();
+        transport.set_capabilities(Some(Capabilities {
+            version: PROTOCOL_VERSION,
+            supported: u32::MAX,
+            width: 320,
+            height: 240,
+            max_report_size: MAX_HID_EVENT_SIZE as u8,
+        }));
+        let config: DeviceConfig = toml::from_str("vid = 0xfeed\npid = 0x0001").unwrap();
+        let handler = HidHandler::with_transport(&config, Box::new(transport.clone())).await;
+        // Hello is seq 0, then the refresh sends the layout and clock
+        let sent: Vec<(EventType, u8)> = transport
+            .frames()
+            .iter()
+            .map(|(header, _)| (header.event_type, header.seq))
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                (EventType::Hello, 0),
+                (EventType::Layout, 1),
+                (EventType::Clock, 2)
+            ]
+        );
+        transport.take_reports();
+        (handler, transport)
+    }
+
+    /// Publish `event` and return the reports written for it.
+    async fn publish(
+        handler: &mut HidHandler,
+        transport: &MemoryTransport,
+        event: impl HidEvent + 'static,
+    ) -> Vec<HidEventImpl> {
+        handler.publish_hid_event(Arc::new(event)).await;
+        transport.take_reports()
+    }
+
+    #[test]
+    fn support_is_per_device() {
+        let support = SupportedEvents::new();
+        // Two unnamed devices with the same vid:pid share a label
+        support.set(0, Some(EventType::Clock.bit()));
+        support.set(1, Some(EventType::AlbumArt.bit()));
+        assert!(support.any(EventType::Clock) && support.any(EventType::AlbumArt));
+        support.set(0, None);
+        assert!(!support.any(EventType::Clock) && support.any(EventType::AlbumArt));
+    }
+
+    #[test]
+    fn older_firmware_misses_changed_payloads() {
+        let v1 = Capabilities {
+            version: 1,
+            supported: u32::MAX,
+            ..Capabilities::LEGACY
+        };
+        assert!(v1.supports(EventType::Clock));
+        for changed in [
+            EventType::MediaUpdate,
+            EventType::MediaUpdateShufflePlay,
+            EventType::PCUpdate,
+            EventType::RawString,
+        ] {
+            assert!(!v1.supports(changed));
+            assert!(!Capabilities::LEGACY.supports(changed));
+        }
+        let current = Capabilities {
+            version: PROTOCOL_VERSION,
+            ..v1
+        };
+        assert!(current.supports(EventType::PCUpdate));
+        assert!(current.supports(EventType::RawString));
+    }
+
+    #[tokio::test]
+    async fn replug_replays_the_whole_state() {
+        let (mut handler, transport) = connected().await;
+        let process = |name: &str| Process {
+            name: name.into(),
+            pid: 1,
+            is_running: true,
+            metadata: None,
+            icon_qgf: None,
+        };
+        let ts6 = |nickname: &str, message: Option<&str>| Ts6HidEvent {
+            nickname: nickname.into(),
+            message: message.map(Into::into),
+            talking: true,
+            show: true,
+            is_self: false,
+        };
+        publish(&mut handler, &transport, process("steam.exe")).await;
+        publish(&mut handler, &transport, process("discord.exe")).await;
+        publish(&mut handler, &transport, ts6("alice", None)).await;
+        publish(&mut handler, &transport, ts6("bob", Some("gg"))).await;
+
+        // Unplugged: the next write fails, but the event is still kept
+        transport.set_plugged_in(false);
+        publish(&mut handler, &transport, process("bf6.exe")).await;
+        assert!(!handler.is_connected());
+
+        transport.set_plugged_in(true);
+        handler.try_connect().await;
+        let replayed: Vec<(EventType, Vec<u8>)> = transport
+            .frames()
+            .into_iter()
+            .map(|(header, payload)| (header.event_type, payload))
+            .filter(|(t, _)| !matches!(t, EventType::Hello | EventType::Layout | EventType::Clock))
+            .collect();
+        // Every process and talk status, but not the chat message
+        assert_eq!(
+            replayed,
+            [
+                (EventType::ProcessStateUpdate, process("bf6.exe").to_bytes()),
+                (
+                    EventType::ProcessStateUpdate,
+                    process("discord.exe").to_bytes()
+                ),
+                (
+                    EventType::ProcessStateUpdate,
+                    process("steam.exe").to_bytes()
+                ),
+                (EventType::TS6, ts6("alice", None).to_bytes()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn media_frame() {
+        let (mut handler, transport) = connected().await;
+        let media = MediaInfo {
+            title: Some("A title long enough to need two reports".into()),
+            artist: Some("Band".into()),
+            album: Some("Album".into()),
+            is_shuffle: Some(true),
+            timeline: None,
+        };
+        assert_eq!(
+            publish(&mut handler, &transport, media).await,
+            [
+                report(&[0xFA, 0x00, 0xF0, 0x01, 3, 48, 0, 0x0C, 0xA4]),
+                report(b"A title long enough to need two "),
+                report(b"reports\nBand\n\n\x01\n"),
+                report(&FOOTER_REPORT),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn process_frame() {
+        let (mut handler, transport) = connected().await;
+        let process = Process {
+            name: "steam.exe".into(),
+            pid: 0x1234,
+            is_running: true,
+            metadata: None,
+            icon_qgf: None,
+        };
+        assert_eq!(
+            publish(&mut handler, &transport, process).await,
+            [
+                report(&[0xFA, 0x00, 0xF0, 0x03, 3, 19, 0, 0xFA, 0x80]),
+                report(b"steam.exe\n\x34\x12\n\x01\n\x00\x00\x00\x00"),
+                report(&FOOTER_REPORT),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pc_stats_frame() {
+        let (mut handler, transport) = connected().await;
+        let stats = PCStatMsg {
+            cpu_percent: 42.5,
+            ram_used_bytes: 4 << 30,
+            ram_total_bytes: 16 << 30,
+        };
+        assert_eq!(
+            publish(&mut handler, &transport, stats).await,
+            [
+                report(&[0xFA, 0x00, 0xF0, 0x04, 3, 16, 0, 0xA9, 0xA9]),
+                // 42.50%, 25.00%, 4096 MiB, 16384 MiB
+                report(b"\x9A\x10\n\xC4\x09\n\x00\x10\x00\x00\n\x00\x40\x00\x00\n"),
+                report(&FOOTER_REPORT),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ts6_frame() {
+        let (mut handler, transport) = connected().await;
+        let ts = Ts6HidEvent {
+            nickname: "alice".into(),
+            message: Some("hi".into()),
+            talking: false,
+            show: true,
+            is_self: false,
+        };
+        assert_eq!(
+            publish(&mut handler, &transport, ts).await,
+            [
+                report(&[0xFA, 0x00, 0xF0, 0x06, 3, 14, 0, 0x45, 0x7B]),
+                report(b"alice\nhi\n\x00\n\x01\n\x00"),
+                report(&FOOTER_REPORT),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn clock_frame() {
+        let (mut handler, transport) = connected().await;
+        let time = clock::Time {
+            hours: 23,
+            minutes: 59,
+            seconds: 30,
+            year: 126,
+            month: 9,
+            day: 18,
+        };
+        assert_eq!(
+            publish(&mut handler, &transport, time).await,
+            [
+                report(&[0xFA, 0x00, 0xF0, 0x07, 3, 6, 0, 0xFF, 0x79]),
+                report(&[126, 9, 18, 23, 59, 30]),
+                report(&FOOTER_REPORT),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn sequence_ids_advance() {
+        let (mut handler, transport) = connected().await;
+        for seq in 3..6 {
+            let time = clock::Time {
+                hours: 0,
+                minutes: 0,
+                seconds: seq,
+                year: 126,
+                month: 0,
+                day: 1,
+            };
+            let reports = publish(&mut handler, &transport, time).await;
+            assert_eq!(reports[0][SEQ_BIT], seq);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Acting on the current media player: MPRIS on Linux, the Windows media
+//! session manager on Windows. `now_playing` only observes; everything that
+//! wants to change playback (keyboard commands, the tray menu, ...) goes
+//! through `perform` or the wrappers below.
+//!
+//! Both backends are blocking, so each call runs on the blocking pool.
+
+use std::time::Duration;
+
+use crate::nostd_types::DeviceCommand;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaAction {
+    PlayPause,
+    Next,
+    Previous,
+    /// Jump to this position in the current track.
+    Seek(Duration),
+    ToggleShuffle,
+    /// 0.0 to 1.0.
+    SetVolume(f64),
+}
+
+impl MediaAction {
+    /// The action for a keyboard command, if it is a media one.
+    pub fn from_command(cmd: DeviceCommand) -> Option<Self> {
+        match cmd {
+            DeviceCommand::MediaPlayPause => Some(MediaAction::PlayPause),
+            DeviceCommand::MediaNext => Some(MediaAction::Next),
+            DeviceCommand::MediaPrevious => Some(MediaAction::Previous),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaAction::PlayPause => "play/pause",
+            MediaAction::Next => "next",
+            MediaAction::Previous => "previous",
+            MediaAction::Seek(_) => "seek",
+            MediaAction::ToggleShuffle => "shuffle",
+            MediaAction::SetVolume(_) => "volume",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MediaControlError {
+    #[error("no media player is active")]
+    NoPlayer,
+    #[error("the player doesn't support {0}")]
+    Unsupported(&'static str),
+    #[error("{0}")]
+    Backend(String),
+}
+
+pub async fn play_pause() -> Result<(), MediaControlError> {
+    perform(MediaAction::PlayPause).await
+}
+
+pub async fn next() -> Result<(), MediaControlError> {
+    perform(MediaAction::Next).await
+}
+
+pub async fn previous() -> Result<(), MediaControlError> {
+    perform(MediaAction::Previous).await
+}
+
+pub async fn seek(position: Duration) -> Result<(), MediaControlError> {
+    perform(MediaAction::Seek(position)).await
+}
+
+pub async fn toggle_shuffle() -> Result<(), MediaControlError> {
+    perform(MediaAction::ToggleShuffle).await
+}
+
+pub async fn set_volume(volume: f64) -> Result<(), MediaControlError> {
+    perform(MediaAction::SetVolume(volume)).await
+}
+
+/// Apply `action` to the active player.
+pub async fn perform(action: MediaAction) -> Result<(), MediaControlError> {
+    tokio::task::spawn_blocking(move || perform_blocking(action))
+        .await
+        .map_err(|e| MediaControlError::Backend(e.to_string()))?
+}
+
+/// Run `action` in the background, logging rather than returning failures.
+/// For callers with nowhere to report an error, like a key press.
+pub fn spawn(action: MediaAction) {
+    tokio::spawn(async move {
+        if let Err(e) = perform(action).await {
+            eprintln!("Media {} failed: {e}", action.name());
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn perform_blocking(action: MediaAction) -> Result<(), MediaControlError> {
+    use crate::background::players;
+    use mpris::PlayerFinder;
+
+    let backend = |e: mpris::DBusError| MediaControlError::Backend(e.to_string());
+    let finder = PlayerFinder::new().map_err(|e| MediaControlError::Backend(e.to_string()))?;
+    // The player on the display, so keys act on what the user can see
+    let player = players::find_current(&finder).ok_or(MediaControlError::NoPlayer)?;
+    let done = match action {
+        MediaAction::PlayPause => player.checked_play_pause(),
+        MediaAction::Next => player.checked_next(),
+        MediaAction::Previous => player.checked_previous(),
+        MediaAction::Seek(position) => {
+            let track_id = player
+                .get_metadata()
+                .map_err(backend)?
+                .track_id()
+                .ok_or(MediaControlError::Unsupported(action.name()))?;
+            player.checked_set_position(track_id, &position)
+        }
+        MediaAction::ToggleShuffle => {
+            let shuffle = player.get_shuffle().map_err(backend)?;
+            player.checked_set_shuffle(!shuffle)
+        }
+        MediaAction::SetVolume(volume) => player.checked_set_volume(volume.clamp(0.0, 1.0)),
+    }
+    .map_err(backend)?;
+    if done {
+        Ok(())
+    } else {
+        Err(MediaControlError::Unsupported(action.name()))
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn perform_blocking(action: MediaAction) -> Result<(), MediaControlError> {
+    use windows::Media::Control::GlobalSystemMediaTransportControlsSessionManager as SessionManager;
+
+    let backend = |e: windows::core::Error| MediaControlError::Backend(e.to_string());
+    let manager = SessionManager::RequestAsync()
+        .and_then(|op| op.get())
+        .map_err(backend)?;
+    let session = manager
+        .GetCurrentSession()
+        .map_err(|_| MediaControlError::NoPlayer)?;
+    let request = match action {
+        MediaAction::PlayPause => session.TryTogglePlayPauseAsync(),
+        MediaAction::Next => session.TrySkipNextAsync(),
+        MediaAction::Previous => session.TrySkipPreviousAsync(),
+        // Positions are TimeSpans, in 100ns ticks
+        MediaAction::Seek(position) => {
+            session.TryChangePlaybackPositionAsync((position.as_nanos() / 100) as i64)
+        }
+        MediaAction::ToggleShuffle => {
+            let shuffle = session
+                .GetPlaybackInfo()
+                .and_then(|info| info.IsShuffleActive())
+                .and_then(|active| active.Value())
+                .unwrap_or(false);
+            session.TryChangeShuffleActiveAsync(!shuffle)
+        }
+        // Sessions don't expose the player's volume
+        MediaAction::SetVolume(_) => return Err(MediaControlError::Unsupported(action.name())),
+    };
+    if request.and_then(|op| op.get()).map_err(backend)? {
+        Ok(())
+    } else {
+        Err(MediaControlError::Unsupported(action.name()))
+    }
+}
@@ -1,9 +1,17 @@
+pub mod alerts;
+pub mod art_cache;
+pub mod capture;
 pub mod clock;
+pub mod commands;
 pub mod hid;
+pub mod media_control;
 pub mod now_playing;
 pub mod pc_stats;
+pub mod players;
 pub mod process_watcher;
 pub mod qgf_art;
+pub mod queue;
+pub mod transport;
 pub mod ts6;
 
 /// Encode text for the HID protocol. SPLIT_CHAR is '\n', so any newline in
@@ -1,8 +1,12 @@
-use crate::background::{qgf_art, sanitize_hid_text};
+use crate::background::art_cache::ArtCache;
+use crate::background::qgf_art::{self, Thumbnails};
+use crate::background::{hid::SupportedEvents, players, sanitize_hid_text};
+use crate::config::PlayerConfig;
 use crate::nostd_types::SPLIT_CHAR;
-use crate::nostd_types::{EventType, FOOTER, HEADER};
+use crate::nostd_types::{
+    EventType, Layout, PlayState, PlaybackState, RepeatMode, Timeline, content_hash,
+};
 use crate::types::HidEvent;
-use image::ImageReader;
 use tokio::sync::mpsc::{self};
 
 #[cfg(target_os = "windows")]
@@ -10,9 +14,9 @@ use gsmtc::{ManagerEvent::*, SessionUpdateEvent::*};
 
 #[cfg(target_os = "linux")]
 use mpris::PlayerFinder;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
-use std::{fmt::Display, io::Cursor, sync::Arc};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::{fmt::Display, sync::Arc};
 
 #[derive(Debug, Clone)]
 pub struct MediaInfo {
@@ -20,28 +24,24 @@ pub struct MediaInfo {
     pub artist: Option<String>,
     pub album: Option<String>,
     pub is_shuffle: Option<bool>,
-    pub artwork: Option<Vec<u8>>,
-    /// QGF-encoded album art built via the qmk-qgf crate; not transmitted yet.
-    pub artwork_qgf: Option<Vec<u8>>,
+    /// Where playback was when the track update was read.
+    pub timeline: Option<Timeline>,
 }
 
+/// How often the position is re-sent while nothing else changes, so the
+/// firmware's own extrapolation can't drift far.
+const POSITION_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
 impl Display for MediaInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "MediaInfo {{ title: {:?}, artist: {:?}, album: {:?}, is_shuffle: {:?}, artwork: [{} bytes], artwork_qgf: [{} bytes] }}",
+            "MediaInfo {{ title: {:?}, artist: {:?}, album: {:?}, is_shuffle: {:?}, timeline: {:?} }}",
             self.title,
             self.artist,
             self.album,
             self.is_shuffle,
-            match &self.artwork {
-                Some(art) => art.len(),
-                None => 0,
-            },
-            match &self.artwork_qgf {
-                Some(art) => art.len(),
-                None => 0,
-            }
+            self.timeline
         )
     }
 }
@@ -65,53 +65,96 @@ impl HidEvent for MediaInfo {
             bytes.push(if is_shuffle { 1 } else { 0 });
         }
         bytes.push(SPLIT_CHAR);
-        if let Some(artwork) = &self.artwork {
-            bytes.extend_from_slice(artwork);
+        if let Some(timeline) = &self.timeline {
+            bytes.extend_from_slice(&timeline.to_bytes());
         }
         bytes
     }
 
-    fn chunks(&self) -> Vec<Vec<u8>> {
-        let buffer = self.to_bytes();
-        let chunk_size = 32;
-        let mut offset = 0;
-        let mut chunks = Vec::new();
-        let mut header_chunk = Vec::new();
-        header_chunk.extend_from_slice(&HEADER); // Header
-        if self.title.is_some() {
-            header_chunk.extend_from_slice(&[EventType::MediaUpdate as u8]);
-        } else {
-            header_chunk.extend_from_slice(&[EventType::MediaUpdateShufflePlay as u8]);
-        }
-        chunks.push(header_chunk);
-        while offset < buffer.len() {
-            let mut chunk = Vec::new();
-
-            let end = std::cmp::min(offset + chunk_size, buffer.len());
-            chunk.extend_from_slice(&buffer[offset..end]);
-            if chunk.len() < 32 {
-                chunk.resize(32, 0);
-            }
-            chunks.push(chunk);
-            offset += chunk_size;
+    fn event_type(&self) -> crate::nostd_types::EventType {
+        EventType::MediaUpdate
+    }
+}
+
+impl HidEvent for PlayState {
+    fn to_bytes(&self) -> Vec<u8> {
+        PlayState::to_bytes(self).to_vec()
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::MediaUpdateShufflePlay
+    }
+}
+
+impl HidEvent for Timeline {
+    fn to_bytes(&self) -> Vec<u8> {
+        Timeline::to_bytes(self).to_vec()
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::MediaPosition
+    }
+}
+
+/// QGF album art for the current track, drawn in the `ALBUM_ART` region of
+/// the MUSIC screen space. An empty image clears the region.
+pub struct AlbumArt {
+    /// The art thumbnailed for each `album_art` region size in use. Each
+    /// device gets the one that fits its layout.
+    pub thumbnails: Thumbnails,
+}
+
+impl AlbumArt {
+    pub fn new(thumbnails: Option<Thumbnails>) -> Self {
+        AlbumArt {
+            thumbnails: thumbnails.unwrap_or_default(),
         }
-        let mut footer_chunk = Vec::new();
-        footer_chunk.extend_from_slice(&FOOTER);
-        chunks.push(footer_chunk);
+    }
+
+    /// The QGF image drawn into a region of `size`, or an empty one.
+    fn qgf_for(&self, size: (u32, u32)) -> &[u8] {
+        self.thumbnails
+            .iter()
+            .find(|(s, _)| *s == size)
+            .map_or(&[], |(_, qgf)| qgf)
+    }
+}
 
-        chunks
+impl HidEvent for AlbumArt {
+    fn to_bytes(&self) -> Vec<u8> {
+        let qgf = self.thumbnails.first().map_or(&[][..], |(_, qgf)| qgf);
+        qgf_art::transfer_bytes(qgf)
     }
 
-    fn event_type(&self) -> crate::nostd_types::EventType {
-        crate::nostd_types::EventType::MediaUpdate
+    fn to_bytes_for(&self, layout: &Layout) -> Vec<u8> {
+        qgf_art::transfer_bytes(self.qgf_for(region_size(layout)))
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::AlbumArt
+    }
+
+    fn content_hash(&self) -> Option<u32> {
+        let hashes: Vec<u8> = self
+            .thumbnails
+            .iter()
+            .flat_map(|(_, qgf)| content_hash(qgf).to_le_bytes())
+            .collect();
+        Some(content_hash(&hashes))
     }
 }
 
 #[cfg(target_os = "windows")]
 pub async fn poll_now_playing(
     resp: mpsc::Sender<Arc<dyn HidEvent>>,
+    support: Arc<SupportedEvents>,
+    art_cache: ArtCache,
+    // The session manager picks the current session on Windows
+    _players: PlayerConfig,
     shutting_down: Arc<AtomicBool>,
 ) {
+    // Bumped on every track update, so late art for an old track is dropped
+    let current_track = Arc::new(AtomicU64::new(0));
     loop {
         if shutting_down.load(Ordering::Relaxed) {
             break;
@@ -130,46 +173,86 @@ pub async fn poll_now_playing(
                         source,
                     } => {
                         let mut last_touched: i64 = 0;
-                        while let Some(evt) = rx.recv().await {
+                        // The last timeline sent and when, to extrapolate from
+                        let mut last_timeline: Option<(Timeline, Instant)> = None;
+                        let mut last_play_state: Option<PlayState> = None;
+                        let mut sync = tokio::time::interval(POSITION_SYNC_INTERVAL);
+                        loop {
+                            let evt = tokio::select! {
+                                evt = rx.recv() => match evt {
+                                    Some(evt) => evt,
+                                    None => break,
+                                },
+                                _ = sync.tick() => {
+                                    if let Some((timeline, at)) = last_timeline {
+                                        let elapsed = at.elapsed().as_millis() as u32;
+                                        resp.send(Arc::new(timeline.advanced(elapsed))).await.ok();
+                                    }
+                                    continue;
+                                }
+                            };
                             match evt {
                                 Media(model, image) => {
+                                    let timeline = gsmtc_timeline(&model);
+                                    let timeline_changed =
+                                        timeline != last_timeline.map(|(t, _)| t);
+                                    if timeline_changed {
+                                        last_timeline = timeline.map(|t| (t, Instant::now()));
+                                        sync.reset();
+                                    }
+                                    let play_state = gsmtc_play_state(&model);
+                                    if play_state != last_play_state {
+                                        last_play_state = play_state;
+                                        if let Some(play_state) = play_state {
+                                            resp.send(Arc::new(play_state)).await.ok();
+                                        }
+                                    }
                                     if let Some(timelime) = model.timeline {
                                         if timelime.last_updated_at_ms == last_touched {
-                                            continue; // we see event duplication, so account for that here.
+                                            // we see event duplication, so account for that here;
+                                            // only the timeline may have moved.
+                                            if let Some(timeline) = timeline.filter(|_| timeline_changed) {
+                                                resp.send(Arc::new(timeline)).await.ok();
+                                            }
+                                            continue;
                                         }
                                         last_touched = timelime.last_updated_at_ms;
                                     }
-                                    let mut artwork_qgf: Option<Vec<u8>> = None;
-                                    if let Some(image) = image {
-                                        let cursor = Cursor::new(&image.data);
-                                        let Ok(img_reader) =
-                                            ImageReader::new(cursor).with_guessed_format()
-                                        else {
-                                            //println!("Could not read image data");
-                                            continue;
-                                        };
-                                        let Ok(mut image) = img_reader.decode() else {
-                                            //println!("Could not decode image data");
-                                            continue;
-                                        };
-
-                                        image = image.thumbnail(50, 50);
-                                        artwork_qgf = qgf_art::image_to_qgf(&image).ok();
-                                    }
+                                    // Don't spend time encoding art no device can show
+                                    let image = image.filter(|_| {
+                                        art_cache.wanted() && support.any(EventType::AlbumArt)
+                                    });
+                                    let image =
+                                        image.map(|i| (ArtCache::content_key(&i.data), i.data));
                                     if let Some(media) = model.media {
+                                        let track =
+                                            current_track.fetch_add(1, Ordering::Relaxed) + 1;
                                         let mut media_info = MediaInfo {
                                             title: Some(sanitize_hid_text(&media.title)),
                                             artist: Some(sanitize_hid_text(&media.artist)),
-                                            is_shuffle: None,
+                                            is_shuffle: play_state.map(|p| p.shuffle),
                                             album: None,
-                                            artwork: None,
-                                            artwork_qgf,
+                                            timeline,
                                         };
                                         if let Some(album) = media.album {
                                             media_info.album =
                                                 Some(sanitize_hid_text(&album.title));
                                         }
+                                        // The old art is cleared and the new one follows
+                                        // once read from the cache or encoded, so the
+                                        // session's events aren't held up on disk access.
                                         resp.send(Arc::new(media_info)).await.ok();
+                                        resp.send(Arc::new(AlbumArt::new(None))).await.ok();
+                                        if let Some((key, data)) = image {
+                                            tokio::spawn(encode_art(
+                                                key,
+                                                data,
+                                                art_cache.clone(),
+                                                resp.clone(),
+                                                current_track.clone(),
+                                                track,
+                                            ));
+                                        }
                                     }
                                 }
                                 _ => {}
@@ -192,14 +275,201 @@ pub async fn poll_now_playing(
     }
 }
 
-/// Fetch album art bytes from an MPRIS art URL. These are commonly `file://`
-/// URIs pointing at a local cache, so handle those without HTTP.
+/// The session's play state and position. gsmtc reports timeline values as
+/// WinRT TimeSpans, in 100ns ticks.
+#[cfg(target_os = "windows")]
+fn gsmtc_timeline(model: &gsmtc::SessionModel) -> Option<Timeline> {
+    let timeline = model.timeline.as_ref()?;
+    let state = match model.playback.as_ref().map(|p| &p.status) {
+        Some(gsmtc::PlaybackStatus::Playing) => PlaybackState::Playing,
+        Some(gsmtc::PlaybackStatus::Paused) => PlaybackState::Paused,
+        _ => PlaybackState::Stopped,
+    };
+    let ms = |ticks: i64| (ticks.max(0) / 10_000).min(u32::MAX as i64) as u32;
+    Some(Timeline {
+        state,
+        position_ms: ms(timeline.position - timeline.start),
+        duration_ms: ms(timeline.end - timeline.start),
+    })
+}
+
+/// The session's play state, shuffle and repeat modes.
+#[cfg(target_os = "windows")]
+fn gsmtc_play_state(model: &gsmtc::SessionModel) -> Option<PlayState> {
+    let playback = model.playback.as_ref()?;
+    Some(PlayState {
+        state: match playback.status {
+            gsmtc::PlaybackStatus::Playing => PlaybackState::Playing,
+            gsmtc::PlaybackStatus::Paused => PlaybackState::Paused,
+            _ => PlaybackState::Stopped,
+        },
+        shuffle: playback.shuffle,
+        repeat: match playback.auto_repeat {
+            gsmtc::AutoRepeatMode::Track => RepeatMode::Track,
+            gsmtc::AutoRepeatMode::List => RepeatMode::Playlist,
+            _ => RepeatMode::Off,
+        },
+    })
+}
+
+/// The size of a layout's `album_art` region.
+fn region_size(layout: &Layout) -> (u32, u32) {
+    (layout.album_art.width() as u32, layout.album_art.height() as u32)
+}
+
+/// Thumbnail bounds for album art: one per distinct `album_art` region among
+/// `layouts`, so art is never bigger than the region it's drawn in. Layouts
+/// without an art region need none.
+pub fn album_art_sizes<'a>(layouts: impl IntoIterator<Item = &'a Layout>) -> Vec<(u32, u32)> {
+    let mut sizes = Vec::new();
+    for size in layouts.into_iter().map(region_size) {
+        if size.0 > 0 && size.1 > 0 && !sizes.contains(&size) {
+            sizes.push(size);
+        }
+    }
+    sizes
+}
+
+/// How often the MPRIS tracker wakes to check for changes.
+#[cfg(target_os = "linux")]
+const PROGRESS_TICK_MS: u32 = 250;
+
+/// How often to look for a player that should take over from the current
+/// one.
 #[cfg(target_os = "linux")]
-fn fetch_art(url: &str) -> Option<Vec<u8>> {
-    if let Some(path) = url.strip_prefix("file://") {
-        std::fs::read(percent_decode_path(path)).ok()
-    } else {
-        reqwest::blocking::get(url).ok()?.bytes().ok().map(|b| b.to_vec())
+const PLAYER_SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+#[cfg(target_os = "linux")]
+fn mpris_state(progress: &mpris::Progress) -> PlaybackState {
+    match progress.playback_status() {
+        mpris::PlaybackStatus::Playing => PlaybackState::Playing,
+        mpris::PlaybackStatus::Paused => PlaybackState::Paused,
+        mpris::PlaybackStatus::Stopped => PlaybackState::Stopped,
+    }
+}
+
+/// The player's state and position, extrapolated to now.
+#[cfg(target_os = "linux")]
+fn mpris_timeline(progress: &mpris::Progress) -> Timeline {
+    let ms = |d: Duration| d.as_millis().min(u32::MAX as u128) as u32;
+    Timeline {
+        state: mpris_state(progress),
+        position_ms: ms(progress.position()),
+        duration_ms: progress.length().map(ms).unwrap_or(0),
+    }
+}
+
+/// The player's play state, shuffle and repeat modes.
+#[cfg(target_os = "linux")]
+fn mpris_play_state(progress: &mpris::Progress) -> PlayState {
+    PlayState {
+        state: mpris_state(progress),
+        shuffle: progress.shuffle(),
+        repeat: match progress.loop_status() {
+            mpris::LoopStatus::None => RepeatMode::Off,
+            mpris::LoopStatus::Track => RepeatMode::Track,
+            mpris::LoopStatus::Playlist => RepeatMode::Playlist,
+        },
+    }
+}
+
+/// Where a track's art comes from. MPRIS art URLs are commonly `file://`
+/// URIs pointing at a local cache; players reuse those paths for different
+/// covers, so local art is keyed by its content rather than its URL.
+#[cfg(target_os = "linux")]
+enum ArtSource {
+    Local { key: String, image: Vec<u8> },
+    Remote(String),
+}
+
+#[cfg(target_os = "linux")]
+impl ArtSource {
+    fn new(url: &str) -> Option<Self> {
+        match url.strip_prefix("file://") {
+            Some(path) => {
+                let image = std::fs::read(percent_decode_path(path)).ok()?;
+                Some(ArtSource::Local {
+                    key: ArtCache::content_key(&image),
+                    image,
+                })
+            }
+            None => Some(ArtSource::Remote(url.to_string())),
+        }
+    }
+
+    fn key(&self) -> &str {
+        match self {
+            ArtSource::Local { key, .. } => key,
+            ArtSource::Remote(url) => url,
+        }
+    }
+}
+
+/// How long a download may take before the track goes without art.
+#[cfg(target_os = "linux")]
+const ART_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Art bigger than this is left undownloaded.
+#[cfg(target_os = "linux")]
+const MAX_ART_BYTES: usize = 16 * 1024 * 1024;
+
+#[cfg(target_os = "linux")]
+async fn download_art(url: &str) -> Option<Vec<u8>> {
+    let mut response = reqwest::get(url).await.ok()?.error_for_status().ok()?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_ART_BYTES as u64)
+    {
+        return None;
+    }
+    // The length is only a hint, so keep count while reading too
+    let mut image = Vec::new();
+    while let Some(chunk) = response.chunk().await.ok()? {
+        if image.len() + chunk.len() > MAX_ART_BYTES {
+            return None;
+        }
+        image.extend_from_slice(&chunk);
+    }
+    Some(image)
+}
+
+/// Fetch art that isn't cached yet and hand it to `encode_art`.
+#[cfg(target_os = "linux")]
+async fn load_art(
+    source: ArtSource,
+    art_cache: ArtCache,
+    resp: mpsc::Sender<Arc<dyn HidEvent>>,
+    current_track: Arc<AtomicU64>,
+    track: u64,
+) {
+    let (key, image) = match source {
+        ArtSource::Local { key, image } => (key, Some(image)),
+        ArtSource::Remote(url) => {
+            let image = tokio::time::timeout(ART_FETCH_TIMEOUT, download_art(&url))
+                .await
+                .ok()
+                .flatten();
+            (url, image)
+        }
+    };
+    if let Some(image) = image {
+        encode_art(key, image, art_cache, resp, current_track, track).await;
+    }
+}
+
+/// Read art from the cache, or encode and cache it, then send it if the
+/// track it belongs to, number `track`, is still the one playing.
+async fn encode_art(
+    key: String,
+    image: Vec<u8>,
+    art_cache: ArtCache,
+    resp: mpsc::Sender<Arc<dyn HidEvent>>,
+    current_track: Arc<AtomicU64>,
+    track: u64,
+) {
+    let art = art_cache.get_or_encode(key, image).await;
+    if art.is_some() && current_track.load(Ordering::Relaxed) == track {
+        resp.send(Arc::new(AlbumArt::new(art))).await.ok();
     }
 }
 
@@ -230,12 +500,22 @@ fn percent_decode_path(s: &str) -> std::path::PathBuf {
 #[cfg(target_os = "linux")]
 pub async fn poll_now_playing(
     resp: mpsc::Sender<Arc<dyn HidEvent>>,
+    support: Arc<SupportedEvents>,
+    art_cache: ArtCache,
+    players: PlayerConfig,
     shutting_down: Arc<AtomicBool>,
 ) {
     // The mpris API is blocking and its D-Bus handles are not Send, so run
     // the whole poll loop on one blocking thread instead of holding them
     // across await points.
+    let runtime = tokio::runtime::Handle::current();
+    // Bumped on every track change, so late art for an old track is dropped
+    let current_track = Arc::new(AtomicU64::new(0));
     let _ = tokio::task::spawn_blocking(move || {
+        players::set_rules(players.clone());
+        let mut selector = players::PlayerSelector::new(players);
+        // Set when a scan decides to switch players
+        let mut next_player = None;
         loop {
             if shutting_down.load(Ordering::Relaxed) {
                 return;
@@ -249,70 +529,145 @@ pub async fn poll_now_playing(
                     continue;
                 }
             };
-            let player = match finder.find_active() {
-                Ok(p) => p,
-                Err(e) => {
-                    eprintln!("Could not find active player: {e}");
-                    std::thread::sleep(Duration::from_secs(10));
+            let player = match next_player
+                .take()
+                .or_else(|| players::select(&finder, &mut selector, None))
+            {
+                Some(p) => p,
+                None => {
+                    // Nothing (allowed) is running; look again shortly
+                    players::set_current(None);
+                    std::thread::sleep(PLAYER_SCAN_INTERVAL);
                     continue;
                 }
             };
-            let events = match player.events() {
-                Ok(e) => e,
+            players::set_current(Some(player.bus_name()));
+            // Wakes every PROGRESS_TICK_MS, or sooner when the player changes
+            let mut tracker = match player.track_progress(PROGRESS_TICK_MS) {
+                Ok(t) => t,
                 Err(e) => {
-                    eprintln!("Could not start event stream: {e}");
+                    eprintln!("Could not track player progress: {e}");
                     std::thread::sleep(Duration::from_secs(10));
                     continue;
                 }
             };
+            let mut last_track = None;
+            let mut last_play_state = None;
+            let mut last_sync = Instant::now();
+            let mut last_scan = Instant::now();
 
-            for event in events {
+            loop {
                 if shutting_down.load(Ordering::Relaxed) {
                     return;
                 }
-                match event {
-                    Ok(mpris::Event::TrackChanged(track)) => {
-                        let title = track.title().map(sanitize_hid_text);
-                        let artist = track.artists().map(|a| sanitize_hid_text(&a.join(", ")));
-                        let album = track.album_name().map(sanitize_hid_text);
-                        let is_shuffle = player.get_shuffle().unwrap_or(false);
-
-                        let mut artwork = None;
-                        let mut artwork_qgf = None;
-                        if let Some(url) = track.art_url() {
-                            if let Some(bytes) = fetch_art(url) {
-                                let cursor = Cursor::new(&bytes);
-                                if let Ok(img_reader) =
-                                    ImageReader::new(cursor).with_guessed_format()
-                                {
-                                    if let Ok(mut image) = img_reader.decode() {
-                                        image = image.thumbnail(50, 50);
-                                        artwork_qgf = qgf_art::image_to_qgf(&image).ok();
-                                        artwork = Some(image.to_rgba8().into_raw());
-                                    }
-                                }
-                            }
-                        }
-                        let media_info = MediaInfo {
-                            title,
-                            artist,
-                            album,
-                            is_shuffle: Some(is_shuffle),
-                            artwork,
-                            artwork_qgf,
-                        };
-                        if resp.blocking_send(Arc::new(media_info)).is_err() {
-                            // Receiver gone — we're shutting down
-                            return;
-                        }
+                let tick = tracker.tick();
+                if tick.player_quit {
+                    break;
+                }
+                if last_scan.elapsed() >= PLAYER_SCAN_INTERVAL {
+                    last_scan = Instant::now();
+                    if let Some(other) =
+                        players::select(&finder, &mut selector, Some(player.bus_name()))
+                        && other.bus_name() != player.bus_name()
+                    {
+                        next_player = Some(other);
+                        break;
+                    }
+                }
+                let progress = tick.progress;
+                let timeline = mpris_timeline(progress);
+                let metadata = progress.metadata();
+                let title = metadata.title().map(sanitize_hid_text);
+                let artist = metadata.artists().map(|a| sanitize_hid_text(&a.join(", ")));
+                let album = metadata.album_name().map(sanitize_hid_text);
+                let track = (title.clone(), artist.clone(), album.clone());
+                let play_state = mpris_play_state(progress);
+
+                if last_track.as_ref() != Some(&track) {
+                    let track_no = current_track.fetch_add(1, Ordering::Relaxed) + 1;
+                    // Don't spend time fetching art no device can show
+                    let source = metadata
+                        .art_url()
+                        .filter(|_| art_cache.wanted() && support.any(EventType::AlbumArt))
+                        .and_then(ArtSource::new);
+                    let cached = source.as_ref().and_then(|s| art_cache.get(s.key()));
+                    let media_info = MediaInfo {
+                        title,
+                        artist,
+                        album,
+                        is_shuffle: Some(play_state.shuffle),
+                        timeline: Some(timeline),
+                    };
+                    last_track = Some(track);
+                    last_sync = Instant::now();
+                    // Cached art goes out with the track. Otherwise the old
+                    // art is cleared and the new one follows once loaded, so
+                    // a slow download never holds the track up.
+                    let art = AlbumArt::new(cached.clone());
+                    if resp.blocking_send(Arc::new(media_info)).is_err()
+                        || resp.blocking_send(Arc::new(art)).is_err()
+                    {
+                        // Receiver gone — we're shutting down
+                        return;
+                    }
+                    if cached.is_none()
+                        && let Some(source) = source
+                    {
+                        runtime.spawn(load_art(
+                            source,
+                            art_cache.clone(),
+                            resp.clone(),
+                            current_track.clone(),
+                            track_no,
+                        ));
+                    }
+                } else if tick.progress_changed || last_sync.elapsed() >= POSITION_SYNC_INTERVAL {
+                    // Same track; keep the progress bar in step
+                    last_sync = Instant::now();
+                    if resp.blocking_send(Arc::new(timeline)).is_err() {
+                        return;
+                    }
+                }
+
+                // Play/pause, shuffle and repeat go out on their own, without
+                // the track
+                if last_play_state != Some(play_state) {
+                    last_play_state = Some(play_state);
+                    if resp.blocking_send(Arc::new(play_state)).is_err() {
+                        return;
                     }
-                    Ok(event) => println!("Event: {:?}", event),
-                    Err(e) => println!("Error receiving event: {}", e),
                 }
             }
-            // The event stream ended (player exited) — loop around and look
-            // for a new active player.
+            // The player exited or another should take over — loop around
+            // and pick again.
         }
     })
     .await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostd_types::{DEFAULT_LAYOUT, ScreenSpace};
+
+    fn with_art(x2: u16, y2: u16) -> Layout {
+        Layout {
+            album_art: ScreenSpace { x: 0, y: 0, x2, y2 },
+            ..DEFAULT_LAYOUT
+        }
+    }
+
+    #[test]
+    fn one_size_per_art_region() {
+        let layouts = [with_art(50, 50), with_art(80, 60), with_art(50, 50), with_art(0, 0)];
+        assert_eq!(album_art_sizes(&layouts), vec![(50, 50), (80, 60)]);
+    }
+
+    #[test]
+    fn each_device_gets_its_own_size() {
+        let art = AlbumArt::new(Some(vec![((50, 50), vec![1; 4]), ((80, 60), vec![2; 6])]));
+        assert_eq!(art.to_bytes_for(&with_art(80, 60)), qgf_art::transfer_bytes(&[2; 6]));
+        assert_eq!(art.to_bytes_for(&with_art(50, 50)), qgf_art::transfer_bytes(&[1; 4]));
+        assert_eq!(art.to_bytes_for(&with_art(20, 20)), qgf_art::transfer_bytes(&[]));
+    }
+}
@@ -1,60 +1,255 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use tokio::sync::Mutex;
 use tokio::sync::mpsc;
 
-use crate::nostd_types::{CPU, EventType, FOOTER, HEADER, RAM, SPLIT_CHAR};
+use crate::background::alerts::{Alerts, Metrics};
+use crate::background::sanitize_hid_text;
+use crate::config::{Config, CpuHistoryConfig, NetworkConfig, SensorConfig};
+use crate::nostd_types::{
+    EventType, MAX_CPU_HISTORY_LEN, MAX_CPU_HISTORY_SERIES, MAX_NET_INTERFACES,
+    MAX_SENSOR_READINGS, PERCENT_MAX, SPLIT_CHAR, pack_rate,
+};
+use crate::stats::system_stats::{self, InterfaceRate, Reading, Sensors, Throughput};
 use crate::types::HidEvent;
-use sysinfo::System;
+use sysinfo::{MINIMUM_CPU_UPDATE_INTERVAL, System};
+use tokio::time::MissedTickBehavior;
 
 #[derive(Debug, Clone)]
-pub struct PCState {}
+pub struct PCState {
+    sensors: SensorConfig,
+    network: NetworkConfig,
+    cpu_history_interval: Duration,
+    cpu_history: CpuHistory,
+    alerts: Alerts,
+}
+
+/// The last few CPU usage samples, per core or for the busiest core.
+#[derive(Debug, Clone)]
+pub struct CpuHistory {
+    length: usize,
+    per_core: bool,
+    series: Vec<VecDeque<u8>>,
+    /// Whether dropping the cores past `MAX_CPU_HISTORY_SERIES` was logged.
+    warned_cores: bool,
+}
+
+impl CpuHistory {
+    pub fn new(length: usize, per_core: bool) -> Self {
+        CpuHistory {
+            length: length.clamp(1, MAX_CPU_HISTORY_LEN),
+            per_core,
+            series: Vec::new(),
+            warned_cores: false,
+        }
+    }
+
+    /// Add a sample, given each core's usage from 0 to 100.
+    pub fn push(&mut self, core_usage: &[f32]) {
+        let percent = |usage: f32| usage.round().clamp(0.0, 100.0) as u8;
+        let sample: Vec<u8> = if self.per_core {
+            if core_usage.len() > MAX_CPU_HISTORY_SERIES && !self.warned_cores {
+                eprintln!(
+                    "CPU history shows the first {MAX_CPU_HISTORY_SERIES} of {} cores",
+                    core_usage.len()
+                );
+                self.warned_cores = true;
+            }
+            core_usage
+                .iter()
+                .take(MAX_CPU_HISTORY_SERIES)
+                .map(|u| percent(*u))
+                .collect()
+        } else {
+            vec![percent(core_usage.iter().copied().fold(0.0, f32::max))]
+        };
+        if sample.len() != self.series.len() {
+            // First sample, or the core count changed
+            self.series = vec![VecDeque::with_capacity(self.length); sample.len()];
+        }
+        for (series, value) in self.series.iter_mut().zip(sample) {
+            if series.len() == self.length {
+                series.pop_front();
+            }
+            series.push_back(value);
+        }
+    }
+
+    /// Every series, padded at the front with zeros to the full length.
+    pub fn snapshot(&self) -> CpuHistoryMsg {
+        let series = self
+            .series
+            .iter()
+            .map(|s| {
+                let mut samples = vec![0; self.length - s.len()];
+                samples.extend(s);
+                samples
+            })
+            .collect();
+        CpuHistoryMsg {
+            length: self.length as u8,
+            series,
+        }
+    }
+}
 
+/// Current CPU and RAM usage. Sent as fixed-point percentages plus the
+/// absolute RAM figures, so the firmware decides how to draw them.
 pub struct PCStatMsg {
-    pub cpu: u16,
-    pub ram: u16,
+    /// Overall CPU usage, 0 to 100.
+    pub cpu_percent: f32,
+    pub ram_used_bytes: u64,
+    pub ram_total_bytes: u64,
+}
+
+impl PCStatMsg {
+    fn ram_percent(&self) -> f32 {
+        if self.ram_total_bytes == 0 {
+            return 0.0;
+        }
+        self.ram_used_bytes as f32 / self.ram_total_bytes as f32 * 100.0
+    }
+}
+
+/// A percentage as hundredths of a percent, clamped to `PERCENT_MAX`.
+fn fixed_percent(percent: f32) -> u16 {
+    (percent * 100.0).round().clamp(0.0, PERCENT_MAX as f32) as u16
+}
+
+fn mib(bytes: u64) -> u32 {
+    (bytes >> 20).min(u32::MAX as u64) as u32
 }
 
 impl HidEvent for PCStatMsg {
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
-        for b in self.cpu.to_le_bytes() {
-            bytes.push(b);
+        bytes.extend_from_slice(&fixed_percent(self.cpu_percent).to_le_bytes());
+        bytes.push(SPLIT_CHAR);
+        bytes.extend_from_slice(&fixed_percent(self.ram_percent()).to_le_bytes());
+        bytes.push(SPLIT_CHAR);
+        bytes.extend_from_slice(&mib(self.ram_used_bytes).to_le_bytes());
+        bytes.push(SPLIT_CHAR);
+        bytes.extend_from_slice(&mib(self.ram_total_bytes).to_le_bytes());
+        bytes.push(SPLIT_CHAR);
+        bytes
+    }
+
+    fn event_type(&self) -> crate::nostd_types::EventType {
+        EventType::PCUpdate
+    }
+}
+
+/// The temperature and fan readings selected in config.toml.
+pub struct SensorMsg {
+    pub temperatures: Vec<Reading>,
+    pub fans: Vec<Reading>,
+}
+
+impl HidEvent for SensorMsg {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.temperatures.len() as u8, self.fans.len() as u8];
+        for t in &self.temperatures {
+            bytes.extend_from_slice(sanitize_hid_text(&t.label).as_bytes());
+            bytes.push(SPLIT_CHAR);
+            // tenths of a degree
+            let tenths = (t.value * 10.0)
+                .round()
+                .clamp(i16::MIN as f32, i16::MAX as f32);
+            bytes.extend_from_slice(&(tenths as i16).to_le_bytes());
         }
-        bytes.push(SPLIT_CHAR as u8);
-        for b in self.ram.to_le_bytes() {
-            bytes.push(b);
+        for f in &self.fans {
+            bytes.extend_from_slice(sanitize_hid_text(&f.label).as_bytes());
+            bytes.push(SPLIT_CHAR);
+            let rpm = f.value.round().clamp(0.0, u16::MAX as f32) as u16;
+            bytes.extend_from_slice(&rpm.to_le_bytes());
         }
-        bytes.push(SPLIT_CHAR as u8);
+        bytes
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::Sensors
+    }
+}
 
-        bytes.resize(32, 0);
+/// Network and disk rates, each packed into two bytes with `pack_rate`.
+pub struct ThroughputMsg {
+    /// Bytes per second.
+    pub disk_read: u64,
+    pub disk_write: u64,
+    pub interfaces: Vec<InterfaceRate>,
+}
 
+impl HidEvent for ThroughputMsg {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&pack_rate(self.disk_read).to_le_bytes());
+        bytes.extend_from_slice(&pack_rate(self.disk_write).to_le_bytes());
+        bytes.push(self.interfaces.len() as u8);
+        for i in &self.interfaces {
+            bytes.extend_from_slice(sanitize_hid_text(&i.name).as_bytes());
+            bytes.push(SPLIT_CHAR);
+            bytes.extend_from_slice(&pack_rate(i.rx).to_le_bytes());
+            bytes.extend_from_slice(&pack_rate(i.tx).to_le_bytes());
+        }
         bytes
     }
 
-    fn chunks(&self) -> Vec<Vec<u8>> {
-        let mut v = Vec::new();
-        let mut header = Vec::new();
-        header.extend_from_slice(&HEADER);
-        header.extend_from_slice(&[EventType::PCUpdate as u8]);
-        v.push(header);
+    fn event_type(&self) -> EventType {
+        EventType::Throughput
+    }
+}
 
-        v.push(self.to_bytes());
-        let mut footer = Vec::new();
-        footer.extend_from_slice(&FOOTER);
-        v.push(footer);
-        v
+/// Refresh CPU usage, unless the last refresh was too recent for a new one
+/// to measure anything. The stats and history ticks often fire together, and
+/// then share one sample.
+fn refresh_cpu_usage(system: &mut System, last_refresh: &mut Option<Instant>) {
+    if last_refresh.is_some_and(|t| t.elapsed() < MINIMUM_CPU_UPDATE_INTERVAL) {
+        return;
     }
+    system.refresh_cpu_usage();
+    *last_refresh = Some(Instant::now());
+}
 
-    fn event_type(&self) -> crate::nostd_types::EventType {
-        EventType::PCUpdate
+/// CPU usage history; each series is `length` whole percentages, oldest
+/// first.
+pub struct CpuHistoryMsg {
+    pub length: u8,
+    pub series: Vec<Vec<u8>>,
+}
+
+impl HidEvent for CpuHistoryMsg {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.series.len() as u8, self.length];
+        for series in &self.series {
+            bytes.extend_from_slice(series);
+        }
+        bytes
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::CpuHistory
     }
 }
+
 impl PCState {
-    pub fn init() -> Self {
-        PCState {}
+    pub fn init(config: &Config) -> Self {
+        let CpuHistoryConfig {
+            interval_ms,
+            length,
+            per_core,
+        } = config.cpu_history;
+        PCState {
+            sensors: config.sensors.clone(),
+            network: config.network.clone(),
+            // Usage sampled more often than this isn't meaningful
+            cpu_history_interval: Duration::from_millis(interval_ms)
+                .max(MINIMUM_CPU_UPDATE_INTERVAL),
+            cpu_history: CpuHistory::new(length, per_core),
+            alerts: Alerts::new(config.alerts.clone()),
+        }
     }
     pub async fn poll_pc_stats(
         &mut self,
@@ -68,28 +263,123 @@ impl PCState {
         tokio::time::sleep(Duration::from_millis(250)).await;
         let max_ram: u64 = system.total_memory();
         drop(system);
+        let mut sensors = Sensors::new();
+        let mut throughput = Throughput::new();
+        let mut stats_tick = tokio::time::interval(Duration::from_secs(1));
+        stats_tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut history_tick = tokio::time::interval(self.cpu_history_interval);
+        history_tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_cpu_refresh = None;
 
         loop {
             if shutting_down.load(Ordering::Relaxed) {
                 return Ok(());
             }
+            let stats_due = tokio::select! {
+                _ = stats_tick.tick() => true,
+                _ = history_tick.tick() => false,
+            };
+            if !stats_due {
+                system = sys.lock().await;
+                refresh_cpu_usage(&mut system, &mut last_cpu_refresh);
+                let usage: Vec<f32> = system.cpus().iter().map(|c| c.cpu_usage()).collect();
+                drop(system);
+                self.cpu_history.push(&usage);
+                if let Err(e) = resp.send(Arc::new(self.cpu_history.snapshot())).await {
+                    return Err(e.to_string());
+                }
+                continue;
+            }
+
             system = sys.lock().await;
-            system.refresh_cpu_usage();
+            refresh_cpu_usage(&mut system, &mut last_cpu_refresh);
             system.refresh_memory();
-            let cpu = system.global_cpu_usage();
-            let ram = system.used_memory();
-            let curr_cpu_pixel_height = (cpu / 100.0) * (CPU.y2 - CPU.y) as f32;
-            let cpu = curr_cpu_pixel_height.round() as u16;
-
-            let curr_ram_pixel_height = (ram as f32 / max_ram as f32) * (RAM.y2 - RAM.y) as f32;
-            let ram = curr_ram_pixel_height.round() as u16;
-            match resp.send(Arc::new(PCStatMsg { cpu, ram })).await {
+            let msg = PCStatMsg {
+                cpu_percent: system.global_cpu_usage(),
+                ram_used_bytes: system.used_memory(),
+                ram_total_bytes: max_ram,
+            };
+            let (cpu_percent, ram_percent) = (msg.cpu_percent, msg.ram_percent());
+            match resp.send(Arc::new(msg)).await {
                 Ok(_) => {}
                 Err(e) => return Err(e.to_string()),
             };
 
             drop(system);
-            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            sensors.refresh();
+            let temperatures = sensors.temperatures();
+            let fans = sensors.fans();
+            let msg = SensorMsg {
+                temperatures: system_stats::select(
+                    &temperatures,
+                    self.sensors.temperatures.as_deref(),
+                    MAX_SENSOR_READINGS,
+                ),
+                fans: system_stats::select(
+                    &fans,
+                    self.sensors.fans.as_deref(),
+                    MAX_SENSOR_READINGS,
+                ),
+            };
+            // Nothing to show on platforms without sensors
+            if !(msg.temperatures.is_empty() && msg.fans.is_empty())
+                && let Err(e) = resp.send(Arc::new(msg)).await
+            {
+                return Err(e.to_string());
+            }
+
+            let metrics = Metrics {
+                cpu_percent,
+                ram_percent,
+                temperatures: &temperatures,
+                fans: &fans,
+            };
+            if let Some(msg) = self.alerts.update(&metrics, Instant::now())
+                && let Err(e) = resp.send(Arc::new(msg)).await
+            {
+                return Err(e.to_string());
+            }
+
+            let sample = throughput.sample();
+            let msg = ThroughputMsg {
+                disk_read: sample.disk_read,
+                disk_write: sample.disk_write,
+                interfaces: system_stats::select_interfaces(
+                    &sample.interfaces,
+                    self.network.interfaces.as_deref(),
+                    MAX_NET_INTERFACES,
+                ),
+            };
+            if let Err(e) = resp.send(Arc::new(msg)).await {
+                return Err(e.to_string());
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_history_per_core_is_capped() {
+        let mut history = CpuHistory::new(4, true);
+        let usage: Vec<f32> = (0..24).map(|core| core as f32).collect();
+        history.push(&usage);
+        history.push(&usage);
+        let snapshot = history.snapshot();
+        assert_eq!(snapshot.series.len(), MAX_CPU_HISTORY_SERIES);
+        assert_eq!(snapshot.series[15], [0, 0, 15, 15]);
+        assert!(history.warned_cores);
+    }
+
+    #[test]
+    fn cpu_history_busiest_core() {
+        let mut history = CpuHistory::new(2, false);
+        history.push(&[10.0, 80.4, 30.0]);
+        history.push(&[120.0, 0.0]);
+        history.push(&[5.0, 6.6]);
+        assert_eq!(history.snapshot().series, [[100, 7]]);
+    }
+}
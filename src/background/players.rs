@@ -0,0 +1,254 @@
+//! Picking which media player to show when several are running, following
+//! the `[players]` rules in config.toml. `now_playing` rescans every few
+//! seconds and switches when the rules say so; `media_control` acts on
+//! whichever player it last picked, or picks one by the same rules.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::config::PlayerConfig;
+
+/// A running player, as the rules see it.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub bus_name: String,
+    pub identity: String,
+    pub playing: bool,
+}
+
+impl Candidate {
+    fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_lowercase();
+        self.identity.to_lowercase().contains(&pattern)
+            || self.bus_name.to_lowercase().contains(&pattern)
+    }
+}
+
+pub struct PlayerSelector {
+    config: PlayerConfig,
+    /// Bus names of the players that were playing at the last scan, to spot
+    /// the ones that have just started.
+    was_playing: HashSet<String>,
+}
+
+impl PlayerSelector {
+    pub fn new(config: PlayerConfig) -> Self {
+        PlayerSelector {
+            config,
+            was_playing: HashSet::new(),
+        }
+    }
+
+    fn allows(&self, c: &Candidate) -> bool {
+        let allowed = match &self.config.allow {
+            Some(allow) => allow.iter().any(|p| c.matches(p)),
+            None => true,
+        };
+        allowed && !self.config.deny.iter().any(|p| c.matches(p))
+    }
+
+    /// Position in `priority`; unlisted players come after every listed one.
+    fn rank(&self, c: &Candidate) -> usize {
+        let priority = &self.config.priority;
+        priority
+            .iter()
+            .position(|p| c.matches(p))
+            .unwrap_or(priority.len())
+    }
+
+    /// Which of `candidates` to show, given the bus name of the one shown
+    /// now. Call once per scan: it also records who was playing.
+    pub fn choose(&mut self, candidates: &[Candidate], current: Option<&str>) -> Option<usize> {
+        let allowed: Vec<usize> = (0..candidates.len())
+            .filter(|i| self.allows(&candidates[*i]))
+            .collect();
+        let started: HashSet<usize> = allowed
+            .iter()
+            .copied()
+            .filter(|i| {
+                candidates[*i].playing && !self.was_playing.contains(&candidates[*i].bus_name)
+            })
+            .collect();
+        self.was_playing = candidates
+            .iter()
+            .filter(|c| c.playing)
+            .map(|c| c.bus_name.clone())
+            .collect();
+
+        // Best ranked of the allowed players that pass `filter`; earlier
+        // candidates win ties
+        let best = |filter: &dyn Fn(usize) -> bool| {
+            allowed
+                .iter()
+                .copied()
+                .filter(|i| filter(*i))
+                .min_by_key(|i| self.rank(&candidates[*i]))
+        };
+        let current = allowed
+            .iter()
+            .copied()
+            .find(|i| Some(candidates[*i].bus_name.as_str()) == current);
+        let playing = |i: usize| candidates[i].playing;
+
+        if self.config.follow_playing {
+            if let Some(i) = best(&|i| started.contains(&i) && Some(i) != current) {
+                return Some(i);
+            }
+            if let Some(c) = current.filter(|c| playing(*c)) {
+                return Some(c);
+            }
+            return best(&playing).or(current).or_else(|| best(&|_| true));
+        }
+
+        let Some(c) = current else {
+            return best(&playing).or_else(|| best(&|_| true));
+        };
+        // A paused player gives way to an equally ranked one that's playing
+        let current_rank = self.rank(&candidates[c]);
+        let outranks = |i: usize| {
+            let rank = self.rank(&candidates[i]);
+            rank < current_rank || (rank == current_rank && !playing(c))
+        };
+        Some(best(&|i| playing(i) && i != c && outranks(i)).unwrap_or(c))
+    }
+}
+
+/// Bus name of the player being shown, for `media_control`.
+static CURRENT: Mutex<Option<String>> = Mutex::new(None);
+
+/// The `[players]` rules `now_playing` follows, for `media_control` to pick
+/// by when there's no current player.
+static RULES: Mutex<Option<PlayerConfig>> = Mutex::new(None);
+
+pub fn set_rules(config: PlayerConfig) {
+    *RULES.lock().unwrap() = Some(config);
+}
+
+pub fn set_current(bus_name: Option<&str>) {
+    *CURRENT.lock().unwrap() = bus_name.map(str::to_string);
+}
+
+pub fn current() -> Option<String> {
+    CURRENT.lock().unwrap().clone()
+}
+
+/// Scan the running players and pick one with `selector`.
+#[cfg(target_os = "linux")]
+pub fn select(
+    finder: &mpris::PlayerFinder,
+    selector: &mut PlayerSelector,
+    current: Option<&str>,
+) -> Option<mpris::Player> {
+    let players = finder.find_all().ok()?;
+    let candidates: Vec<Candidate> = players
+        .iter()
+        .map(|p| Candidate {
+            bus_name: p.bus_name().to_string(),
+            identity: p.identity().to_string(),
+            playing: p
+                .get_playback_status()
+                .is_ok_and(|s| s == mpris::PlaybackStatus::Playing),
+        })
+        .collect();
+    let i = selector.choose(&candidates, current)?;
+    players.into_iter().nth(i)
+}
+
+/// The player `now_playing` is showing. If it hasn't picked one yet, or
+/// that player has since quit, pick one by the same rules.
+#[cfg(target_os = "linux")]
+pub fn find_current(finder: &mpris::PlayerFinder) -> Option<mpris::Player> {
+    if let Some(bus_name) = current()
+        && let Some(player) = finder
+            .find_all()
+            .ok()?
+            .into_iter()
+            .find(|p| p.bus_name() == bus_name)
+    {
+        return Some(player);
+    }
+    let rules = RULES.lock().unwrap().clone().unwrap_or_default();
+    select(finder, &mut PlayerSelector::new(rules), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(bus_name: &str, identity: &str, playing: bool) -> Candidate {
+        Candidate {
+            bus_name: bus_name.into(),
+            identity: identity.into(),
+            playing,
+        }
+    }
+
+    #[test]
+    fn priority_decides_between_playing_players() {
+        let mut selector = PlayerSelector::new(PlayerConfig {
+            priority: vec!["spotify".into(), "firefox".into()],
+            ..Default::default()
+        });
+        let firefox = candidate("org.mpris.MediaPlayer2.firefox", "Mozilla Firefox", true);
+        let paused = candidate("org.mpris.MediaPlayer2.spotify", "Spotify", false);
+        let playing = candidate("org.mpris.MediaPlayer2.spotify", "Spotify", true);
+
+        let players = [firefox.clone(), paused];
+        assert_eq!(selector.choose(&players, None), Some(0));
+        // A paused player doesn't take over, however highly ranked
+        assert_eq!(selector.choose(&players, Some(&firefox.bus_name)), Some(0));
+        // Once it plays, it does
+        let players = [firefox.clone(), playing.clone()];
+        assert_eq!(selector.choose(&players, Some(&firefox.bus_name)), Some(1));
+        // and a lower ranked one playing doesn't take it back
+        assert_eq!(selector.choose(&players, Some(&playing.bus_name)), Some(1));
+    }
+
+    #[test]
+    fn paused_player_gives_way_to_an_equal_one() {
+        let mut selector = PlayerSelector::new(PlayerConfig::default());
+        let players = [candidate("a", "A", false), candidate("b", "B", true)];
+        assert_eq!(selector.choose(&players, Some("a")), Some(1));
+    }
+
+    #[test]
+    fn deny_and_allow() {
+        let mut selector = PlayerSelector::new(PlayerConfig {
+            deny: vec!["kdeconnect".into()],
+            ..Default::default()
+        });
+        let kde = candidate("org.mpris.MediaPlayer2.kdeconnect", "KDE Connect", true);
+        let vlc = candidate("org.mpris.MediaPlayer2.vlc", "VLC media player", false);
+        assert_eq!(selector.choose(&[kde.clone(), vlc.clone()], None), Some(1));
+        assert_eq!(selector.choose(std::slice::from_ref(&kde), None), None);
+        // A denied player isn't kept even if it's the current one
+        assert_eq!(
+            selector.choose(&[kde.clone(), vlc.clone()], Some(&kde.bus_name)),
+            Some(1)
+        );
+
+        let mut selector = PlayerSelector::new(PlayerConfig {
+            allow: Some(vec!["vlc".into()]),
+            ..Default::default()
+        });
+        assert_eq!(selector.choose(&[kde.clone(), vlc], None), Some(1));
+        assert_eq!(selector.choose(&[kde], None), None);
+    }
+
+    #[test]
+    fn follow_mode_switches_to_whatever_starts() {
+        let mut selector = PlayerSelector::new(PlayerConfig {
+            follow_playing: true,
+            ..Default::default()
+        });
+        let players = [candidate("a", "A", true), candidate("b", "B", false)];
+        assert_eq!(selector.choose(&players, None), Some(0));
+        let players = [candidate("a", "A", true), candidate("b", "B", true)];
+        assert_eq!(selector.choose(&players, Some("a")), Some(1));
+        // Still playing, so it stays
+        assert_eq!(selector.choose(&players, Some("b")), Some(1));
+        // Paused: back to one that's playing
+        let players = [candidate("a", "A", true), candidate("b", "B", false)];
+        assert_eq!(selector.choose(&players, Some("b")), Some(0));
+    }
+}
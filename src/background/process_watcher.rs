@@ -1,6 +1,5 @@
 use crate::background::qgf_art;
-use crate::nostd_types::FOOTER;
-use crate::nostd_types::HEADER;
+use crate::background::queue::Coalesce;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -13,7 +12,7 @@ use sysinfo::System;
 use tokio::sync::{Mutex, mpsc};
 use tokio::time::{Duration, sleep};
 
-use crate::nostd_types::EventType;
+use crate::nostd_types::{EventType, content_hash};
 use crate::types::HidEvent;
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Process {
@@ -22,11 +21,20 @@ pub struct Process {
     pub is_running: bool,
     pub metadata: Option<HashMap<String, String>>,
     /// QGF-encoded process icon (from `icons/<stem>.ico`) built via the
-    /// qmk-qgf crate; not transmitted yet.
+    /// qmk-qgf crate. The update carries its hash; the image itself follows
+    /// as a `ProcessIcon` event.
     #[serde(skip)]
     pub icon_qgf: Option<Vec<u8>>,
 }
 
+impl Process {
+    /// Hash of the icon the firmware should draw next to this process, or 0
+    /// when there is none.
+    pub fn icon_hash(&self) -> u32 {
+        self.icon_qgf.as_deref().map(content_hash).unwrap_or(0)
+    }
+}
+
 impl HidEvent for Process {
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
@@ -35,29 +43,43 @@ impl HidEvent for Process {
         bytes.extend_from_slice(&self.pid.to_le_bytes());
         bytes.extend_from_slice(&[SPLIT_CHAR]);
         bytes.extend_from_slice(&(self.is_running as u8).to_le_bytes());
+        bytes.extend_from_slice(&[SPLIT_CHAR]);
+        bytes.extend_from_slice(&self.icon_hash().to_le_bytes());
         bytes
     }
 
-    fn chunks(&self) -> Vec<Vec<u8>> {
-        let mut v = Vec::new();
-        let mut header_chunk = Vec::new();
-        header_chunk.extend_from_slice(&HEADER); // Header
-        header_chunk.extend_from_slice(&[EventType::ProcessStateUpdate as u8]); // Header
-        v.push(header_chunk);
-        let mut chunk = self.to_bytes();
-        if chunk.len() < 32 {
-            chunk.resize(32, 0);
-        }
-        v.push(chunk);
-        let mut footer_chunk = Vec::new();
-        footer_chunk.extend_from_slice(&FOOTER);
-        v.push(footer_chunk);
-        v
-    }
-
     fn event_type(&self) -> crate::nostd_types::EventType {
         EventType::ProcessStateUpdate
     }
+
+    /// Updates for different processes must not replace each other.
+    fn coalesce(&self) -> Coalesce {
+        Coalesce::Key(self.name.clone())
+    }
+}
+
+/// QGF icon for a recognised process. The firmware caches icons by hash, so
+/// the HID handler only sends each one once per connection.
+pub struct ProcessIcon {
+    pub qgf: Vec<u8>,
+}
+
+impl HidEvent for ProcessIcon {
+    fn to_bytes(&self) -> Vec<u8> {
+        qgf_art::transfer_bytes(&self.qgf)
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::ProcessIcon
+    }
+
+    fn content_hash(&self) -> Option<u32> {
+        Some(content_hash(&self.qgf))
+    }
+
+    fn coalesce(&self) -> Coalesce {
+        Coalesce::Key(content_hash(&self.qgf).to_string())
+    }
 }
 
 pub struct ProcessWatcher {
@@ -119,6 +141,7 @@ impl ProcessWatcher {
                                 seen_this_cycle.insert(this_name.clone());
 
                                 let icon_qgf = qgf_art::process_icon_qgf(&this_name);
+                                let icon = icon_qgf.clone().map(|qgf| ProcessIcon { qgf });
                                 if chan
                                     .send(Arc::new(Process {
                                         name: this_name,
@@ -133,6 +156,11 @@ impl ProcessWatcher {
                                     // Receiver gone — we're shutting down
                                     return;
                                 }
+                                if let Some(icon) = icon
+                                    && chan.send(Arc::new(icon)).await.is_err()
+                                {
+                                    return;
+                                }
                             }
                         }
                     }
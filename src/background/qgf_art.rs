@@ -1,10 +1,10 @@
 //! Builds QGF (Quantum Graphics Format) representations of images used by the
 //! background tasks, via the `qmk-qgf` crate (linked as `qgf`).
 //!
-//! Nothing consumes these representations yet — they are built alongside the
-//! existing artwork/process handling so they can be wired up to the HID
-//! transport later.
+//! Album art and process icons are transmitted to the device as
+//! `EventType::AlbumArt` and `EventType::ProcessIcon` events respectively.
 
+use crate::nostd_types::content_hash;
 use image::{DynamicImage, ImageReader};
 use std::io::Cursor;
 use std::path::Path;
@@ -35,6 +35,22 @@ pub fn image_bytes_to_qgf(data: &[u8]) -> Option<Vec<u8>> {
     image_to_qgf(&image).ok()
 }
 
+/// Album art encoded once for each thumbnail size, keyed by that size.
+pub type Thumbnails = Vec<((u32, u32), Vec<u8>)>;
+
+/// Decode raw image bytes once, shrink them to fit within each of `sizes`
+/// (keeping the aspect ratio) and encode every result as QGF.
+pub fn thumbnails_to_qgf(data: &[u8], sizes: &[(u32, u32)]) -> Option<Thumbnails> {
+    let reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?;
+    let image = reader.decode().ok()?;
+    sizes
+        .iter()
+        .map(|&(w, h)| Some(((w, h), image_to_qgf(&image.thumbnail(w, h)).ok()?)))
+        .collect()
+}
+
 /// Build the QGF representation of the .ico associated with a recognised
 /// process. The icon is looked up as `icons/<process stem>.ico` relative to
 /// the working directory (the same place `config.toml` is loaded from), e.g.
@@ -45,3 +61,30 @@ pub fn process_icon_qgf(process_name: &str) -> Option<Vec<u8>> {
     let data = std::fs::read(Path::new("icons").join(format!("{stem}.ico"))).ok()?;
     image_bytes_to_qgf(&data)
 }
+
+/// Length and hash in front of the QGF bytes of an image transfer.
+const TRANSFER_HEADER_LEN: usize = 8;
+
+/// Payload for a QGF image transfer: the image length (u32 LE) and its
+/// `content_hash` (u32 LE), followed by the QGF bytes themselves. An empty
+/// image tells the device to clear whatever it is showing.
+///
+/// A frame holds at most `u16::MAX` payload bytes. Images too big for that
+/// are sent as a clear instead, rather than cut short under a length and hash
+/// that no longer match.
+pub fn transfer_bytes(qgf: &[u8]) -> Vec<u8> {
+    let qgf = if qgf.len() + TRANSFER_HEADER_LEN > u16::MAX as usize {
+        eprintln!(
+            "QGF image is {} bytes, too big to send in one frame; clearing instead",
+            qgf.len()
+        );
+        &[]
+    } else {
+        qgf
+    };
+    let mut bytes = Vec::with_capacity(qgf.len() + TRANSFER_HEADER_LEN);
+    bytes.extend_from_slice(&(qgf.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&content_hash(qgf).to_le_bytes());
+    bytes.extend_from_slice(qgf);
+    bytes
+}
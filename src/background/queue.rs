@@ -0,0 +1,210 @@
+//! Per-device queue between the producers and the HID writer. Only the newest
+//! pending event per key is kept, so a burst of periodic updates can't pile
+//! stale frames up in front of important ones, and higher-priority events are
+//! sent first.
+
+use crate::nostd_types::EventType;
+use crate::types::HidEvent;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Send order between pending events; higher goes first, ties go in arrival
+/// order.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn of(event_type: EventType) -> Self {
+        match event_type {
+            EventType::Clock
+            | EventType::ProcessStateUpdate
+            | EventType::ProcessIcon
+            | EventType::RawString => Priority::High,
+            EventType::PCUpdate
+            | EventType::Sensors
+            | EventType::Throughput
+            | EventType::CpuHistory => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// Which pending event a newer one replaces.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Coalesce {
+    /// The pending event of the same type, if any.
+    Type,
+    /// The pending event of the same type and key, e.g. the same process.
+    Key(String),
+    /// None: every one is sent. For one-off events like chat messages, which
+    /// aren't replayed on reconnect either.
+    Never,
+}
+
+/// Where a pending event is kept; a newer event in the same slot replaces it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Slot {
+    Type(EventType),
+    Key(EventType, String),
+    /// An event that never coalesces, by its arrival order.
+    Own(u64),
+}
+
+struct Pending {
+    seq: u64,
+    priority: Priority,
+    event: Arc<dyn HidEvent>,
+}
+
+#[derive(Default)]
+struct Inner {
+    pending: HashMap<Slot, Pending>,
+    next_seq: u64,
+    closed: bool,
+}
+
+#[derive(Default)]
+pub struct EventQueue {
+    inner: Mutex<Inner>,
+    notify: Notify,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue an event, replacing the pending event it coalesces with.
+    pub fn push(&self, event: Arc<dyn HidEvent>) {
+        let mut inner = self.inner.lock().unwrap();
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        let slot = match event.coalesce() {
+            Coalesce::Type => Slot::Type(event.event_type()),
+            Coalesce::Key(key) => Slot::Key(event.event_type(), key),
+            Coalesce::Never => Slot::Own(seq),
+        };
+        let priority = event.priority();
+        inner.pending.insert(
+            slot,
+            Pending {
+                seq,
+                priority,
+                event,
+            },
+        );
+        drop(inner);
+        self.notify.notify_one();
+    }
+
+    /// Wait for the highest-priority pending event. Returns None once the
+    /// queue is closed and drained.
+    pub async fn pop(&self) -> Option<Arc<dyn HidEvent>> {
+        loop {
+            {
+                let mut inner = self.inner.lock().unwrap();
+                let next = inner
+                    .pending
+                    .iter()
+                    .max_by_key(|(_, p)| (p.priority, std::cmp::Reverse(p.seq)))
+                    .map(|(slot, _)| slot.clone());
+                if let Some(slot) = next {
+                    return inner.pending.remove(&slot).map(|p| p.event);
+                }
+                if inner.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An event whose payload is `id`, to tell them apart once popped.
+    struct Test {
+        id: u8,
+        event_type: EventType,
+        coalesce: Coalesce,
+    }
+
+    impl HidEvent for Test {
+        fn to_bytes(&self) -> Vec<u8> {
+            vec![self.id]
+        }
+
+        fn event_type(&self) -> EventType {
+            self.event_type
+        }
+
+        fn coalesce(&self) -> Coalesce {
+            self.coalesce.clone()
+        }
+    }
+
+    fn push(queue: &EventQueue, id: u8, event_type: EventType, coalesce: Coalesce) {
+        queue.push(Arc::new(Test {
+            id,
+            event_type,
+            coalesce,
+        }));
+    }
+
+    /// Close the queue and pop everything, by id.
+    async fn drain(queue: &EventQueue) -> Vec<u8> {
+        queue.close();
+        let mut ids = Vec::new();
+        while let Some(event) = queue.pop().await {
+            ids.extend(event.to_bytes());
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn higher_priority_first_then_arrival_order() {
+        let queue = EventQueue::new();
+        push(&queue, 1, EventType::PCUpdate, Coalesce::Type);
+        push(&queue, 2, EventType::MediaUpdate, Coalesce::Type);
+        push(&queue, 3, EventType::Clock, Coalesce::Type);
+        push(&queue, 4, EventType::Sensors, Coalesce::Type);
+        push(&queue, 5, EventType::TS6, Coalesce::Type);
+        push(&queue, 6, EventType::RawString, Coalesce::Type);
+        assert_eq!(drain(&queue).await, [3, 6, 2, 5, 1, 4]);
+    }
+
+    #[tokio::test]
+    async fn newer_events_replace_pending_ones() {
+        let queue = EventQueue::new();
+        let key = |k: &str| Coalesce::Key(k.into());
+        push(&queue, 1, EventType::MediaUpdate, Coalesce::Type);
+        push(&queue, 2, EventType::ProcessStateUpdate, key("steam.exe"));
+        push(&queue, 3, EventType::ProcessStateUpdate, key("bf6.exe"));
+        push(&queue, 4, EventType::MediaUpdate, Coalesce::Type);
+        push(&queue, 5, EventType::ProcessStateUpdate, key("steam.exe"));
+        // The replacement keeps its own place in the arrival order
+        assert_eq!(drain(&queue).await, [3, 5, 4]);
+    }
+
+    #[tokio::test]
+    async fn never_coalesced_events_all_arrive() {
+        let queue = EventQueue::new();
+        push(&queue, 1, EventType::TS6, Coalesce::Never);
+        push(&queue, 2, EventType::TS6, Coalesce::Type);
+        push(&queue, 3, EventType::TS6, Coalesce::Never);
+        push(&queue, 4, EventType::TS6, Coalesce::Type);
+        assert_eq!(drain(&queue).await, [1, 3, 4]);
+    }
+}
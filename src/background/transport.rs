@@ -0,0 +1,322 @@
+//! The link between a `HidHandler` and a device. `HidapiTransport` talks to
+//! real hardware; `MemoryTransport` records every report in memory so the
+//! exact frames produced by the background modules can be checked without a
+//! keyboard plugged in. `EmulatorTransport` feeds them to a software display
+//! and writes what it shows to a PNG.
+
+use crate::config::DeviceConfig;
+use crate::emulator::Display;
+use crate::nostd_types::decode::FrameReassembler;
+use crate::nostd_types::{
+    Capabilities, DeviceCommand, EventType, FrameHeader, HidEventImpl, Layout, MAX_HID_EVENT_SIZE,
+};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error("device is not open")]
+    Closed,
+    #[error(transparent)]
+    Hid(#[from] hidapi::HidError),
+    #[error("failed to write emulator image: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+pub trait Transport: Send {
+    /// Write one report to the device.
+    fn write_report(&mut self, report: &HidEventImpl) -> Result<(), TransportError>;
+    /// Read one report from the device without blocking. Ok(None) when
+    /// nothing is pending.
+    fn read_report(&mut self) -> Result<Option<HidEventImpl>, TransportError>;
+    /// Close and try to open the device again. Returns whether it is open.
+    fn reopen(&mut self) -> bool;
+    /// Drop the device handle, e.g. after it has been unplugged.
+    fn close(&mut self);
+    fn is_open(&self) -> bool;
+}
+
+/// True when `info` is the interface described by `config`. A keyboard
+/// exposes several interfaces under one vid/pid (keyboard, consumer control,
+/// raw HID), so usage page/usage pick out the raw HID one.
+fn matches_config(info: &hidapi::DeviceInfo, config: &DeviceConfig) -> bool {
+    info.vendor_id() == config.vid
+        && info.product_id() == config.pid
+        && config.usage_page.is_none_or(|p| info.usage_page() == p)
+        && config.usage.is_none_or(|u| info.usage() == u)
+        && config.product.as_deref().is_none_or(|wanted| {
+            info.product_string()
+                .is_some_and(|product| product.eq_ignore_ascii_case(wanted))
+        })
+}
+
+fn new_device(config: &DeviceConfig) -> Option<hidapi::HidDevice> {
+    let api = match hidapi::HidApi::new() {
+        Ok(api) => api,
+        Err(e) => {
+            eprintln!("Failed to initialise hidapi: {e}");
+            return None;
+        }
+    };
+    // Without a usage filter we can't tell the interfaces apart, so fall back
+    // to the first one that accepts a write.
+    let probe = config.usage_page.is_none() && config.usage.is_none();
+    let devices = api.device_list().filter(|d| matches_config(d, config));
+    for device in devices {
+        let Ok(device) = api.open_path(device.path()) else {
+            continue;
+        };
+        if !probe || device.write(&[0]).is_ok() {
+            return Some(device);
+        }
+    }
+    if let Some(wanted) = &config.product {
+        let products: Vec<&str> = api
+            .device_list()
+            .filter(|d| d.vendor_id() == config.vid && d.product_id() == config.pid)
+            .filter_map(|d| d.product_string())
+            .collect();
+        if !products.is_empty() {
+            eprintln!(
+                "No HID interface on {:04x}:{:04x} has product string {wanted:?} (found {products:?})",
+                config.vid, config.pid
+            );
+        }
+    }
+    None
+}
+
+/// A raw HID interface opened through hidapi.
+pub struct HidapiTransport {
+    config: DeviceConfig,
+    device: Option<hidapi::HidDevice>,
+}
+
+impl HidapiTransport {
+    /// Doesn't open the device; call `reopen` for that.
+    pub fn new(config: &DeviceConfig) -> Self {
+        HidapiTransport {
+            config: config.clone(),
+            device: None,
+        }
+    }
+}
+
+impl Transport for HidapiTransport {
+    fn write_report(&mut self, report: &HidEventImpl) -> Result<(), TransportError> {
+        let device = self.device.as_ref().ok_or(TransportError::Closed)?;
+        // handle report ID
+        let mut out = [0u8; MAX_HID_EVENT_SIZE + 1];
+        out[1..].copy_from_slice(report);
+        device.write(&out)?;
+        Ok(())
+    }
+
+    fn read_report(&mut self) -> Result<Option<HidEventImpl>, TransportError> {
+        let device = self.device.as_ref().ok_or(TransportError::Closed)?;
+        let mut buf = [0u8; MAX_HID_EVENT_SIZE];
+        match device.read_timeout(&mut buf, 0)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf)),
+        }
+    }
+
+    fn reopen(&mut self) -> bool {
+        self.device = new_device(&self.config);
+        self.device.is_some()
+    }
+
+    fn close(&mut self) {
+        self.device = None;
+    }
+
+    fn is_open(&self) -> bool {
+        self.device.is_some()
+    }
+}
+
+#[derive(Default)]
+struct MemoryState {
+    plugged_in: bool,
+    open: bool,
+    written: Vec<HidEventImpl>,
+    to_read: VecDeque<HidEventImpl>,
+    capabilities: Option<Capabilities>,
+}
+
+/// An in-memory device. Clones share the same state, so keep one clone to
+/// inspect what a `HidHandler` wrote to the other.
+#[derive(Clone)]
+pub struct MemoryTransport {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl Default for MemoryTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryTransport {
+    /// A device that is plugged in, but not opened until the handler calls
+    /// `reopen`.
+    pub fn new() -> Self {
+        MemoryTransport {
+            state: Arc::new(Mutex::new(MemoryState {
+                plugged_in: true,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Simulate the device being unplugged (writes fail and `reopen` keeps
+    /// failing) or plugged back in.
+    pub fn set_plugged_in(&self, plugged_in: bool) {
+        self.state.lock().unwrap().plugged_in = plugged_in;
+    }
+
+    /// Answer every Hello with `capabilities`, like current firmware. With
+    /// None (the default) Hellos go unanswered, like firmware that predates
+    /// the handshake.
+    pub fn set_capabilities(&self, capabilities: Option<Capabilities>) {
+        self.state.lock().unwrap().capabilities = capabilities;
+    }
+
+    /// Queue a report for the handler to read, e.g. a `DeviceCommand`.
+    pub fn push_input(&self, report: HidEventImpl) {
+        self.state.lock().unwrap().to_read.push_back(report);
+    }
+
+    /// Every report written so far.
+    pub fn reports(&self) -> Vec<HidEventImpl> {
+        self.state.lock().unwrap().written.clone()
+    }
+
+    /// Take the reports written so far, leaving the record empty.
+    pub fn take_reports(&self) -> Vec<HidEventImpl> {
+        std::mem::take(&mut self.state.lock().unwrap().written)
+    }
+
+    /// The written reports reassembled into frames, as firmware would see
+    /// them. Frames that fail to reassemble are skipped.
+    pub fn frames(&self) -> Vec<(FrameHeader, Vec<u8>)> {
+        let mut reassembler = FrameReassembler::<{ u16::MAX as usize }>::new();
+        self.reports()
+            .iter()
+            .filter_map(|report| match reassembler.push(report) {
+                Some(Ok(frame)) => Some((frame.header, frame.payload.to_vec())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn write_report(&mut self, report: &HidEventImpl) -> Result<(), TransportError> {
+        let mut state = self.state.lock().unwrap();
+        if !(state.open && state.plugged_in) {
+            return Err(TransportError::Closed);
+        }
+        state.written.push(*report);
+        if let Some(caps) = state.capabilities
+            && FrameHeader::parse(report).is_some_and(|h| h.event_type == EventType::Hello)
+        {
+            let reply = DeviceCommand::Capabilities.to_report(&caps.to_args());
+            state.to_read.push_back(reply);
+        }
+        Ok(())
+    }
+
+    fn read_report(&mut self) -> Result<Option<HidEventImpl>, TransportError> {
+        let mut state = self.state.lock().unwrap();
+        if !(state.open && state.plugged_in) {
+            return Err(TransportError::Closed);
+        }
+        Ok(state.to_read.pop_front())
+    }
+
+    fn reopen(&mut self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.open = state.plugged_in;
+        state.open
+    }
+
+    fn close(&mut self) {
+        self.state.lock().unwrap().open = false;
+    }
+
+    fn is_open(&self) -> bool {
+        self.state.lock().unwrap().open
+    }
+}
+
+/// A software display standing in for the keyboard. Every completed frame
+/// re-renders the screen to `path`, so an image viewer that reloads on change
+/// shows the layout live.
+pub struct EmulatorTransport {
+    display: Display,
+    path: PathBuf,
+    open: bool,
+    to_read: VecDeque<HidEventImpl>,
+}
+
+impl EmulatorTransport {
+    /// Render to `path`, starting out with `layout`.
+    pub fn new(path: impl Into<PathBuf>, layout: Layout) -> Self {
+        EmulatorTransport {
+            display: Display::with_layout(layout),
+            path: path.into(),
+            open: false,
+            to_read: VecDeque::new(),
+        }
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+}
+
+impl Transport for EmulatorTransport {
+    fn write_report(&mut self, report: &HidEventImpl) -> Result<(), TransportError> {
+        if !self.open {
+            return Err(TransportError::Closed);
+        }
+        match self.display.push_report(report) {
+            Some(EventType::Hello) => {
+                let caps = self.display.capabilities();
+                self.to_read
+                    .push_back(DeviceCommand::Capabilities.to_report(&caps.to_args()));
+            }
+            Some(_) => {
+                // Write to a temporary file first so a viewer never sees half
+                // a PNG.
+                let tmp = self.path.with_extension("png.tmp");
+                self.display.save_png(&tmp)?;
+                std::fs::rename(&tmp, &self.path).map_err(image::ImageError::IoError)?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn read_report(&mut self) -> Result<Option<HidEventImpl>, TransportError> {
+        if !self.open {
+            return Err(TransportError::Closed);
+        }
+        Ok(self.to_read.pop_front())
+    }
+
+    fn reopen(&mut self) -> bool {
+        self.open = true;
+        true
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+}
@@ -1,6 +1,6 @@
 use crate::background::process_watcher::ProcessWatcher;
 use crate::background::sanitize_hid_text;
-use crate::{background::ts6::types::Ts6HidEvent, types::HidEvent};
+use crate::types::HidEvent;
 use futures_util::{SinkExt, StreamExt};
 use serde_json::json;
 use std::{
@@ -19,8 +19,32 @@ use tokio_tungstenite::{
 
 mod types;
 
+pub use types::Ts6HidEvent;
+
+/// Remote-apps hotkey id for toggling the microphone. Bind it under
+/// Settings -> Hotkeys in the TeamSpeak client.
+const TOGGLE_MUTE_BUTTON: &str = "toggle-mute";
+
+/// Actions the TS6 client can be asked to perform.
+#[derive(Debug, Clone, Copy)]
+pub enum Ts6Command {
+    ToggleMute,
+}
+
+fn button_press(button: &str, state: bool) -> Message {
+    let msg = json!({
+        "type": "buttonPress",
+        "payload": {
+            "button": button,
+            "state": state
+        }
+    });
+    Message::Text(Utf8Bytes::from(msg.to_string()))
+}
+
 pub async fn poll_teamspeak(
     resp: mpsc::Sender<Arc<dyn HidEvent>>,
+    mut commands: mpsc::Receiver<Ts6Command>,
     shutting_down: Arc<AtomicBool>,
     api_key: &str,
     self_name: Option<&str>,
@@ -89,7 +113,24 @@ pub async fn poll_teamspeak(
                 continue 'reconnect;
             }
 
-            let msg = match read.next().await {
+            let next = tokio::select! {
+                next = read.next() => next,
+                Some(cmd) = commands.recv() => {
+                    match cmd {
+                        Ts6Command::ToggleMute => {
+                            // A remote-apps hotkey fires on press and release
+                            for state in [true, false] {
+                                let press = button_press(TOGGLE_MUTE_BUTTON, state);
+                                if let Err(e) = send.send(press).await {
+                                    eprintln!("TS6: failed to send button press: {e}");
+                                }
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
+            let msg = match next {
                 Some(Ok(msg)) => msg,
                 Some(Err(e)) => {
                     eprintln!("WebSocket error: {}", e);
@@ -1,5 +1,6 @@
 use crate::{
-    nostd_types::{EventType, FOOTER, HEADER, SPLIT_CHAR},
+    background::queue::Coalesce,
+    nostd_types::{EventType, SPLIT_CHAR},
     types::HidEvent,
 };
 use serde::{Deserialize, Serialize};
@@ -427,35 +428,57 @@ impl HidEvent for Ts6HidEvent {
         bytes
     }
 
-    fn chunks(&self) -> Vec<Vec<u8>> {
-        let buffer = self.to_bytes();
-        let chunk_size = 32;
-        let mut offset = 0;
-        let mut chunks = Vec::new();
-        let mut header_chunk = Vec::new();
-        header_chunk.extend_from_slice(&HEADER);
-        header_chunk.extend_from_slice(&[EventType::TS6 as u8]);
-
-        chunks.push(header_chunk);
-        while offset < buffer.len() {
-            let mut chunk = Vec::new();
+    fn event_type(&self) -> EventType {
+        EventType::TS6
+    }
 
-            let end = std::cmp::min(offset + chunk_size, buffer.len());
-            chunk.extend_from_slice(&buffer[offset..end]);
-            if chunk.len() < 32 {
-                chunk.resize(32, 0);
-            }
-            chunks.push(chunk);
-            offset += chunk_size;
+    /// Keep the newest talk status per user. Chat messages are never
+    /// coalesced, so a burst of messages from one person all get shown.
+    fn coalesce(&self) -> Coalesce {
+        match self.message {
+            Some(_) => Coalesce::Never,
+            None => Coalesce::Key(format!("talk:{}", self.nickname)),
         }
-        let mut footer_chunk = Vec::new();
-        footer_chunk.extend_from_slice(&FOOTER);
-        chunks.push(footer_chunk);
+    }
+}
 
-        chunks
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::queue::EventQueue;
+    use std::sync::Arc;
+
+    fn event(nickname: &str, message: Option<&str>, talking: bool) -> Arc<dyn HidEvent> {
+        Arc::new(Ts6HidEvent {
+            nickname: nickname.into(),
+            message: message.map(str::to_string),
+            talking,
+            show: true,
+            is_self: false,
+        })
     }
 
-    fn event_type(&self) -> crate::nostd_types::EventType {
-        crate::nostd_types::EventType::TS6
+    #[tokio::test]
+    async fn messages_are_not_coalesced() {
+        let queue = EventQueue::new();
+        queue.push(event("alice", Some("first"), false));
+        queue.push(event("alice", None, true));
+        queue.push(event("alice", Some("second"), false));
+        queue.push(event("alice", None, false));
+        queue.close();
+
+        let mut payloads = Vec::new();
+        while let Some(e) = queue.pop().await {
+            payloads.push(e.to_bytes());
+        }
+        // Both messages, and only the newest talk status
+        assert_eq!(
+            payloads,
+            [
+                b"alice\nfirst\n\x00\n\x01\n\x00".to_vec(),
+                b"alice\nsecond\n\x00\n\x01\n\x00".to_vec(),
+                b"alice\n\n\x00\n\x01\n\x00".to_vec(),
+            ]
+        );
     }
 }
@@ -0,0 +1,146 @@
+//! Generates `slipstream_protocol.h` for the QMK keymap from `nostd_types`,
+//! so the C side never has to hand-copy protocol constants.
+
+use crate::nostd_types::*;
+use std::fmt::Write;
+
+pub const HEADER_FILE_NAME: &str = "slipstream_protocol.h";
+
+fn c_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("0x{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn c_header() -> String {
+    let mut h = String::new();
+    // Writing to a String can't fail, so the fmt::Results below are ignored.
+    let _ = writeln!(
+        h,
+        "// Generated by `slipstream gen-header` from src/nostd_types. Do not edit."
+    );
+    let _ = writeln!(h, "#pragma once\n\n#include <stdint.h>\n");
+
+    let _ = writeln!(
+        h,
+        "#define SLIPSTREAM_MAX_HID_EVENT_SIZE {MAX_HID_EVENT_SIZE}"
+    );
+    let _ = writeln!(h, "#define SLIPSTREAM_TYPE_BIT {TYPE_BIT}");
+    let _ = writeln!(h, "#define SLIPSTREAM_SEQ_BIT {SEQ_BIT}");
+    let _ = writeln!(h, "#define SLIPSTREAM_LEN_BIT {LEN_BIT}");
+    let _ = writeln!(h, "#define SLIPSTREAM_CRC_BIT {CRC_BIT}");
+    let _ = writeln!(h, "#define SLIPSTREAM_HEADER_LEN {HEADER_LEN}");
+    let _ = writeln!(h, "#define SLIPSTREAM_SPLIT_CHAR 0x{SPLIT_CHAR:02X}");
+    let _ = writeln!(h, "#define SLIPSTREAM_PERCENT_MAX {PERCENT_MAX}");
+    let _ = writeln!(h, "#define SLIPSTREAM_RATE_VALUE_BITS {RATE_VALUE_BITS}");
+    let _ = writeln!(
+        h,
+        "#define SLIPSTREAM_MAX_CPU_HISTORY_LEN {MAX_CPU_HISTORY_LEN}"
+    );
+    let _ = writeln!(
+        h,
+        "#define SLIPSTREAM_MAX_CPU_HISTORY_SERIES {MAX_CPU_HISTORY_SERIES}\n"
+    );
+
+    let _ = writeln!(
+        h,
+        "static const uint8_t SLIPSTREAM_HEADER[{}] = {{{}}};",
+        HEADER.len(),
+        c_bytes(&HEADER)
+    );
+    let _ = writeln!(
+        h,
+        "static const uint8_t SLIPSTREAM_FOOTER[{}] = {{{}}};\n",
+        FOOTER.len(),
+        c_bytes(&FOOTER)
+    );
+    let _ = writeln!(
+        h,
+        "static const uint8_t SLIPSTREAM_COMMAND_HEADER[{}] = {{{}}};",
+        COMMAND_HEADER.len(),
+        c_bytes(&COMMAND_HEADER)
+    );
+    let _ = writeln!(h, "#define SLIPSTREAM_COMMAND_BIT {COMMAND_BIT}");
+    let _ = writeln!(h, "#define SLIPSTREAM_PROTOCOL_VERSION {PROTOCOL_VERSION}");
+    let _ = writeln!(
+        h,
+        "#define SLIPSTREAM_CAPABILITIES_ARGS_LEN {}",
+        Capabilities::ARGS_LEN
+    );
+    let _ = writeln!(h, "#define SLIPSTREAM_LAYOUT_LEN {}", Layout::BYTES_LEN);
+    let _ = writeln!(h, "#define SLIPSTREAM_TIMELINE_LEN {}", Timeline::BYTES_LEN);
+    let _ = writeln!(
+        h,
+        "#define SLIPSTREAM_PLAY_STATE_LEN {}\n",
+        PlayState::BYTES_LEN
+    );
+
+    let _ = writeln!(h, "typedef enum {{");
+    for t in EventType::ALL {
+        let _ = writeln!(
+            h,
+            "    SLIPSTREAM_EVENT_{} = 0x{:02X},",
+            t.name().to_uppercase(),
+            t as u8
+        );
+    }
+    let _ = writeln!(h, "}} slipstream_event_type_t;\n");
+
+    let _ = writeln!(h, "typedef enum {{");
+    for c in DeviceCommand::ALL {
+        let _ = writeln!(
+            h,
+            "    SLIPSTREAM_COMMAND_{} = 0x{:02X},",
+            c.name().to_uppercase(),
+            c as u8
+        );
+    }
+    let _ = writeln!(h, "}} slipstream_command_t;\n");
+
+    let _ = writeln!(h, "typedef enum {{");
+    for s in PlaybackState::ALL {
+        let _ = writeln!(
+            h,
+            "    SLIPSTREAM_PLAYBACK_{} = {},",
+            s.name().to_uppercase(),
+            s as u8
+        );
+    }
+    let _ = writeln!(h, "}} slipstream_playback_state_t;\n");
+
+    let _ = writeln!(h, "typedef enum {{");
+    for m in RepeatMode::ALL {
+        let _ = writeln!(
+            h,
+            "    SLIPSTREAM_REPEAT_{} = {},",
+            m.name().to_uppercase(),
+            m as u8
+        );
+    }
+    let _ = writeln!(h, "}} slipstream_repeat_mode_t;\n");
+
+    let _ = writeln!(
+        h,
+        "typedef struct {{\n    uint16_t x;\n    uint16_t y;\n    uint16_t x2;\n    uint16_t y2;\n}} slipstream_screen_space_t;\n"
+    );
+    for (name, space) in SCREEN_SPACES {
+        let _ = writeln!(
+            h,
+            "static const slipstream_screen_space_t SLIPSTREAM_{name} = {{{}, {}, {}, {}}};",
+            space.x, space.y, space.x2, space.y2
+        );
+    }
+
+    // Region numbers, as used by alerts
+    let _ = writeln!(h);
+    for (i, (name, _)) in SCREEN_SPACES.iter().enumerate() {
+        let _ = writeln!(h, "#define SLIPSTREAM_REGION_{name} {i}");
+    }
+    let _ = writeln!(
+        h,
+        "#define SLIPSTREAM_ALERT_NO_REGION 0x{ALERT_NO_REGION:02X}"
+    );
+    h
+}
@@ -0,0 +1,141 @@
+use crate::nostd_types::region_index;
+use serde::Deserialize;
+
+/// What an alert rule watches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Overall CPU usage, in percent.
+    Cpu,
+    /// RAM in use, in percent.
+    Ram,
+    /// A temperature sensor, in degrees Celsius.
+    Temperature,
+    /// A fan, in RPM.
+    Fan,
+}
+
+/// A threshold alert from config.toml, e.g.
+///
+/// ```toml
+/// [[alerts]]
+/// metric = "temperature"
+/// sensor = "Tctl"
+/// above = 90
+/// for_secs = 10
+/// hysteresis = 5
+/// region = "cpu"
+///
+/// [[alerts]]
+/// metric = "ram"
+/// above = 95
+/// message = "RAM nearly full"
+/// ```
+///
+/// The alert is raised once the value has been past `above` (or `below`) for
+/// `for_secs`, and cleared when it comes back past the threshold by
+/// `hysteresis`. `sensor` picks a temperature or fan the same way as
+/// `[sensors]`. `region` is the layout region the firmware should flash.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertRule {
+    pub metric: Metric,
+    pub sensor: Option<String>,
+    pub above: Option<f32>,
+    pub below: Option<f32>,
+    #[serde(default)]
+    pub for_secs: u64,
+    #[serde(default)]
+    pub hysteresis: f32,
+    pub message: Option<String>,
+    pub region: Option<String>,
+}
+
+impl AlertRule {
+    /// Check the rule is complete and names things that exist.
+    pub fn check(&self) -> Result<(), String> {
+        if self.above.is_some() == self.below.is_some() {
+            return Err("needs exactly one of above or below".into());
+        }
+        // NaN compares false both ways, so check it separately
+        if !self.hysteresis.is_finite() || self.hysteresis < 0.0 {
+            return Err("hysteresis must be zero or more".into());
+        }
+        if self.message.as_deref().is_some_and(|m| m.trim().is_empty()) {
+            // An empty message is what clears the alert on the device
+            return Err("message can't be empty".into());
+        }
+        match (self.metric, &self.sensor) {
+            (Metric::Temperature | Metric::Fan, None) => {
+                return Err(format!("{:?} alerts need a sensor", self.metric).to_lowercase());
+            }
+            (Metric::Cpu | Metric::Ram, Some(_)) => {
+                return Err(format!("{:?} alerts don't take a sensor", self.metric).to_lowercase());
+            }
+            _ => {}
+        }
+        if let Some(region) = &self.region
+            && region_index(region).is_none()
+        {
+            return Err(format!("unknown region {region:?}"));
+        }
+        Ok(())
+    }
+
+    /// The configured message, or one describing the rule.
+    pub fn message(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        let (name, unit) = match self.metric {
+            Metric::Cpu => ("CPU", "%"),
+            Metric::Ram => ("RAM", "%"),
+            Metric::Temperature => (self.sensor.as_deref().unwrap_or_default(), "C"),
+            Metric::Fan => (self.sensor.as_deref().unwrap_or_default(), "RPM"),
+        };
+        match (self.above, self.below) {
+            (Some(above), _) => format!("{name} > {above}{unit}"),
+            (_, Some(below)) => format!("{name} < {below}{unit}"),
+            (None, None) => name.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(hysteresis: f32, message: Option<&str>) -> AlertRule {
+        AlertRule {
+            metric: Metric::Cpu,
+            sensor: None,
+            above: Some(90.0),
+            below: None,
+            for_secs: 0,
+            hysteresis,
+            message: message.map(String::from),
+            region: Some("cpu".into()),
+        }
+    }
+
+    #[test]
+    fn check() {
+        assert_eq!(rule(5.0, Some("CPU hot")).check(), Ok(()));
+        assert_eq!(rule(0.0, None).check(), Ok(()));
+        for hysteresis in [-1.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(
+                rule(hysteresis, None).check(),
+                Err("hysteresis must be zero or more".into())
+            );
+        }
+        for message in ["", "  "] {
+            assert_eq!(
+                rule(0.0, Some(message)).check(),
+                Err("message can't be empty".into())
+            );
+        }
+        let mut ram = rule(0.0, None);
+        ram.metric = Metric::Ram;
+        ram.sensor = Some("Tctl".into());
+        assert_eq!(ram.check(), Err("ram alerts don't take a sensor".into()));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::nostd_types::{Layout, ScreenSpace};
+use serde::Deserialize;
+
+/// A named screen layout from config.toml, e.g.
+///
+/// ```toml
+/// [layouts.oled]
+/// width = 128
+/// height = 32
+/// music = [0, 0, 110, 24]
+/// clock = [0, 24, 110, 32]
+/// cpu = [114, 0, 120, 32]
+/// ram = [122, 0, 128, 32]
+/// ```
+///
+/// Regions are `[x, y, x2, y2]`; any that are left out aren't shown.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LayoutConfig {
+    pub width: u16,
+    pub height: u16,
+    pub clock: Option<[u16; 4]>,
+    pub music: Option<[u16; 4]>,
+    pub album_art: Option<[u16; 4]>,
+    pub cpu: Option<[u16; 4]>,
+    pub ram: Option<[u16; 4]>,
+    pub ts: Option<[u16; 4]>,
+    pub ts_bubble: Option<[u16; 4]>,
+}
+
+impl LayoutConfig {
+    /// Build the layout, checking every region is inside the screen.
+    pub fn to_layout(&self) -> Result<Layout, String> {
+        let space = |region: Option<[u16; 4]>| match region {
+            Some([x, y, x2, y2]) => ScreenSpace { x, y, x2, y2 },
+            None => ScreenSpace::EMPTY,
+        };
+        let layout = Layout {
+            width: self.width,
+            height: self.height,
+            clock: space(self.clock),
+            music: space(self.music),
+            album_art: space(self.album_art),
+            cpu: space(self.cpu),
+            ram: space(self.ram),
+            ts: space(self.ts),
+            ts_bubble: space(self.ts_bubble),
+        };
+        for (name, s) in layout.regions() {
+            if s.x > s.x2 || s.y > s.y2 || s.x2 > layout.width || s.y2 > layout.height {
+                return Err(format!(
+                    "{} [{}, {}, {}, {}] doesn't fit a {}x{} screen",
+                    name.to_lowercase(),
+                    s.x,
+                    s.y,
+                    s.x2,
+                    s.y2,
+                    layout.width,
+                    layout.height
+                ));
+            }
+        }
+        Ok(layout)
+    }
+}
@@ -1,4 +1,13 @@
+use crate::nostd_types::{EventType, Layout};
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+mod alert;
+mod layout;
+
+pub use alert::{AlertRule, Metric};
+pub use layout::LayoutConfig;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -9,13 +18,219 @@ pub struct Config {
     pub recognised_processes: Vec<String>,
     pub ts6_api_key: Option<String>,
     pub ts6_self_name: Option<String>,
+    /// Screen layouts by name, for devices whose display isn't the default
+    /// 320x240 one.
+    #[serde(default)]
+    pub layouts: HashMap<String, LayoutConfig>,
+    #[serde(default)]
+    pub sensors: SensorConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub cpu_history: CpuHistoryConfig,
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+    #[serde(default)]
+    pub players: PlayerConfig,
+    #[serde(default)]
+    pub art_cache: ArtCacheConfig,
+}
+
+/// Which sensors to send, e.g.
+///
+/// ```toml
+/// [sensors]
+/// temperatures = ["Tctl", "amdgpu edge"]
+/// fans = ["cpu_fan"]
+/// ```
+///
+/// Each entry matches the first sensor whose label contains it
+/// (case-insensitive). When a list is unset the first few sensors found are
+/// sent.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SensorConfig {
+    pub temperatures: Option<Vec<String>>,
+    pub fans: Option<Vec<String>>,
+}
+
+/// Which network interfaces to report throughput for, by exact name, e.g.
+///
+/// ```toml
+/// [network]
+/// interfaces = ["eth0", "wlan0"]
+/// ```
+///
+/// When unset, the first few interfaces that have carried traffic are sent.
+/// Named interfaces that don't exist (yet) are left out.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NetworkConfig {
+    pub interfaces: Option<Vec<String>>,
+}
+
+/// The CPU history graph, e.g.
+///
+/// ```toml
+/// [cpu_history]
+/// interval_ms = 500
+/// length = 48
+/// per_core = true
+/// ```
+///
+/// Without `per_core` a single series is kept, holding the busiest core at
+/// each sample, so one pegged thread still shows.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CpuHistoryConfig {
+    pub interval_ms: u64,
+    /// Samples kept per series, at most `MAX_CPU_HISTORY_LEN`.
+    pub length: usize,
+    /// One series per core instead of the busiest core. Only the first
+    /// `MAX_CPU_HISTORY_SERIES` (16) cores fit in a report; the rest are left
+    /// out, and a note is logged once.
+    pub per_core: bool,
+}
+
+impl Default for CpuHistoryConfig {
+    fn default() -> Self {
+        CpuHistoryConfig {
+            interval_ms: 1000,
+            length: 32,
+            per_core: false,
+        }
+    }
+}
+
+/// Which MPRIS player to show and control on Linux, e.g.
+///
+/// ```toml
+/// [players]
+/// priority = ["spotify", "mpd"]
+/// deny = ["firefox", "chromium"]
+/// follow_playing = false
+/// ```
+///
+/// Every entry is matched case-insensitively against a player's identity
+/// ("Spotify") and D-Bus name ("org.mpris.MediaPlayer2.spotify"). With
+/// `allow` set, only players matching it are used; players matching `deny`
+/// never are. The current player is kept until one ranked higher in
+/// `priority` starts playing (unlisted players rank last). With
+/// `follow_playing`, whichever player most recently started playing is shown
+/// instead, and `priority` only breaks ties.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PlayerConfig {
+    pub priority: Vec<String>,
+    pub allow: Option<Vec<String>>,
+    pub deny: Vec<String>,
+    pub follow_playing: bool,
+}
+
+/// Where encoded album art is kept between runs, e.g.
+///
+/// ```toml
+/// [art_cache]
+/// dir = "art_cache"
+/// max_mb = 64
+/// ```
+///
+/// `dir` is relative to the working directory, like `icons/`. The least
+/// recently used art is deleted once the cache grows past `max_mb`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ArtCacheConfig {
+    pub dir: PathBuf,
+    pub max_mb: u64,
+}
+
+impl Default for ArtCacheConfig {
+    fn default() -> Self {
+        ArtCacheConfig {
+            dir: PathBuf::from("art_cache"),
+            max_mb: 64,
+        }
+    }
+}
+
+impl Config {
+    /// Look up each device's layout by name and check it fits its screen.
+    /// Call once after loading.
+    pub fn resolve_layouts(&mut self) -> Result<(), String> {
+        for device in &mut self.devices {
+            let Some(name) = &device.layout else {
+                continue;
+            };
+            let layout = self
+                .layouts
+                .get(name)
+                .ok_or_else(|| format!("device {} uses unknown layout {name:?}", device.label()))?;
+            device.resolved_layout = layout
+                .to_layout()
+                .map_err(|e| format!("layout {name:?}: {e}"))?;
+        }
+        Ok(())
+    }
+
+    /// Check every alert rule. Call once after loading.
+    pub fn check_alerts(&self) -> Result<(), String> {
+        for (i, rule) in self.alerts.iter().enumerate() {
+            rule.check().map_err(|e| format!("alert {}: {e}", i + 1))?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct DeviceConfig {
+    /// Shown in logs and dialogs, and used by `slipstream replay --device`.
     pub name: Option<String>,
     pub vid: u16,
     pub pid: u16,
+    /// USB product string to match (case-insensitive), to tell apart
+    /// devices that share a vid/pid. Any product when unset.
+    pub product: Option<String>,
+    /// Usage page/usage of the interface to open; 0xff60/0x61 is QMK's raw
+    /// HID interface.
     pub usage_page: Option<u16>,
     pub usage: Option<u16>,
+    /// Event types this device receives, by name (e.g. "media_update",
+    /// "clock", "ts6"). Every event type when unset.
+    pub events: Option<Vec<String>>,
+    /// Render this device's frames to a PNG at this path instead of opening
+    /// a real device; vid/pid are then only used to label it.
+    pub emulate: Option<String>,
+    /// Record every report sent to this device to a capture file at this
+    /// path, for `slipstream replay`.
+    pub capture: Option<String>,
+    /// Name of an entry in `layouts`. The default 320x240 layout when unset.
+    pub layout: Option<String>,
+    /// The layout named by `layout`, filled in by `Config::resolve_layouts`.
+    #[serde(skip)]
+    pub resolved_layout: Layout,
+}
+
+impl DeviceConfig {
+    /// Human-readable name for logs and dialogs.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{:04x}:{:04x}", self.vid, self.pid),
+        }
+    }
+
+    pub fn subscribes_to(&self, event_type: EventType) -> bool {
+        match &self.events {
+            Some(events) => events.iter().any(|e| e == event_type.name()),
+            None => true,
+        }
+    }
+
+    /// Entries in `events` that don't name an event type.
+    pub fn unknown_events(&self) -> Vec<&str> {
+        self.events
+            .iter()
+            .flatten()
+            .filter(|e| EventType::from_name(e).is_none())
+            .map(String::as_str)
+            .collect()
+    }
 }
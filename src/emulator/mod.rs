@@ -0,0 +1,550 @@
+//! A software stand-in for the keyboard's display. `Display` consumes the
+//! same HID reports the firmware receives, decodes them with
+//! `nostd_types::decode` and renders the screen using the device's `Layout`,
+//! so layouts can be checked (or compared against golden images) without a
+//! keyboard plugged in.
+//!
+//! QGF decoding isn't available on the host, so album art is drawn as a
+//! block coloured by its content hash. An alert outlines the region it
+//! flashes and shows its message in a banner along the bottom. Process
+//! state, sensor readings, throughput and CPU history have no region on
+//! screen and are ignored.
+
+mod font;
+
+use crate::nostd_types::decode::{Event, FrameReassembler};
+use crate::nostd_types::*;
+use image::{ImageResult, Rgb, RgbImage};
+use std::collections::VecDeque;
+use std::path::Path;
+
+const BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+const TEXT: Rgb<u8> = Rgb([255, 255, 255]);
+const DIM_TEXT: Rgb<u8> = Rgb([150, 150, 150]);
+const TALKING: Rgb<u8> = Rgb([80, 220, 100]);
+const CPU_BAR: Rgb<u8> = Rgb([230, 90, 70]);
+const RAM_BAR: Rgb<u8> = Rgb([70, 140, 230]);
+const OUTLINE: Rgb<u8> = Rgb([60, 60, 60]);
+const GUIDE: Rgb<u8> = Rgb([255, 0, 255]);
+const ALERT: Rgb<u8> = Rgb([220, 30, 30]);
+
+/// Height of a line of text in the TS region.
+const TS_LINE_HEIGHT: u32 = 10;
+/// Height of the alert banner.
+const BANNER_HEIGHT: u16 = 11;
+
+#[derive(Default)]
+struct Media {
+    title: String,
+    artist: String,
+    album: String,
+    is_shuffle: bool,
+}
+
+/// Height of the progress bar under the track details.
+const PROGRESS_HEIGHT: u16 = 3;
+
+#[derive(Default)]
+struct ScreenState {
+    layout: Layout,
+    media: Option<Media>,
+    timeline: Option<Timeline>,
+    play_state: Option<PlayState>,
+    album_art: Option<u32>,
+    /// Fixed-point percentages, as sent.
+    cpu: u16,
+    ram: u16,
+    /// Nicknames of the people currently talking, in the order they started.
+    talkers: Vec<String>,
+    /// The most recent chat messages, oldest first.
+    messages: VecDeque<String>,
+    self_talking: bool,
+    clock: Option<String>,
+    /// The region to flash and the message.
+    alert: Option<(Option<u8>, String)>,
+}
+
+pub struct Display {
+    reassembler: Box<FrameReassembler<{ u16::MAX as usize }>>,
+    state: ScreenState,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+impl Display {
+    /// A blank screen with the default layout.
+    pub fn new() -> Self {
+        Self::with_layout(DEFAULT_LAYOUT)
+    }
+
+    /// A blank screen laid out as `layout`, until a Layout event says
+    /// otherwise.
+    pub fn with_layout(layout: Layout) -> Self {
+        Display {
+            reassembler: Box::new(FrameReassembler::new()),
+            state: ScreenState {
+                layout,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.state.layout
+    }
+
+    /// What the emulator tells the host in reply to a Hello: every event
+    /// type, on a screen the size of its layout.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            version: PROTOCOL_VERSION,
+            supported: EventType::ALL.iter().fold(0, |mask, t| mask | t.bit()),
+            width: self.state.layout.width,
+            height: self.state.layout.height,
+            max_report_size: MAX_HID_EVENT_SIZE as u8,
+        }
+    }
+
+    /// Feed one report, as written to the device. Returns the type of the
+    /// frame it completed, if it completed one that decoded.
+    pub fn push_report(&mut self, report: &[u8]) -> Option<EventType> {
+        match self.reassembler.push(report) {
+            Some(Ok(frame)) => {
+                let event = frame.event()?;
+                self.state.apply(event);
+                Some(frame.header.event_type)
+            }
+            Some(Err(e)) => {
+                eprintln!("Emulator dropped a frame: {e:?}");
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Draw the screen as the firmware would.
+    pub fn render(&self) -> RgbImage {
+        let layout = &self.state.layout;
+        let mut img = RgbImage::from_pixel(layout.width as u32, layout.height as u32, BACKGROUND);
+        self.state.draw(&mut img);
+        img
+    }
+
+    /// Like `render`, with every region of the layout outlined and labelled.
+    pub fn render_with_regions(&self) -> RgbImage {
+        let mut img = self.render();
+        for (name, space) in self.state.layout.regions() {
+            outline(&mut img, space, GUIDE);
+            text(
+                &mut img,
+                space.x as u32 + 1,
+                space.y as u32 + 1,
+                name,
+                GUIDE,
+                1,
+            );
+        }
+        img
+    }
+
+    /// Render and write the screen to `path` as a PNG.
+    pub fn save_png(&self, path: &Path) -> ImageResult<()> {
+        self.render()
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+}
+
+impl ScreenState {
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::Media(m) => {
+                self.media = Some(Media {
+                    title: latin1(m.title),
+                    artist: latin1(m.artist),
+                    album: latin1(m.album),
+                    is_shuffle: m.is_shuffle.unwrap_or(false),
+                });
+                self.timeline = m.timeline;
+            }
+            Event::MediaPosition(timeline) => self.timeline = Some(timeline),
+            Event::PlayState(play_state) => {
+                self.play_state = Some(play_state);
+                if let Some(media) = &mut self.media {
+                    media.is_shuffle = play_state.shuffle;
+                }
+                if let Some(timeline) = &mut self.timeline {
+                    timeline.state = play_state.state;
+                }
+            }
+            Event::AlbumArt(art) => {
+                self.album_art = (!art.qgf.is_empty()).then_some(art.hash);
+            }
+            Event::PcStats(stats) => {
+                self.cpu = stats.cpu;
+                self.ram = stats.ram;
+            }
+            Event::Ts6(ts) => {
+                if !ts.show {
+                    // TeamSpeak closed; blank the region.
+                    self.talkers.clear();
+                    self.messages.clear();
+                    self.self_talking = false;
+                } else if ts.is_self {
+                    self.self_talking = ts.talking;
+                } else if !ts.message.is_empty() {
+                    self.messages.push_back(format!(
+                        "{}: {}",
+                        latin1(ts.nickname),
+                        latin1(ts.message)
+                    ));
+                } else {
+                    let nickname = latin1(ts.nickname);
+                    self.talkers.retain(|n| *n != nickname);
+                    if ts.talking {
+                        self.talkers.push(nickname);
+                    }
+                }
+                let lines = (self.layout.ts.height() as u32 / TS_LINE_HEIGHT) as usize;
+                while self.talkers.len() + self.messages.len() > lines
+                    && self.messages.pop_front().is_some()
+                {}
+            }
+            Event::Clock(c) => {
+                self.clock = Some(format!(
+                    "{:02}:{:02}:{:02} {}-{:02}-{:02}",
+                    c.hours,
+                    c.minutes,
+                    c.seconds,
+                    c.year as u16 + 1900,
+                    c.month + 1,
+                    c.day
+                ));
+            }
+            Event::Layout(layout) => self.layout = layout,
+            Event::Alert(alert) => {
+                self.alert = alert.map(|a| (a.region, latin1(a.message)));
+            }
+            Event::Process(_)
+            | Event::ProcessIcon(_)
+            | Event::Hello(_)
+            | Event::Sensors(_)
+            | Event::Throughput(_)
+            | Event::CpuHistory(_) => {}
+        }
+    }
+
+    fn draw(&self, img: &mut RgbImage) {
+        let layout = &self.layout;
+        let art = &layout.album_art;
+        if let Some(hash) = self.album_art {
+            let [r, g, b, _] = hash.to_le_bytes();
+            fill(img, art, Rgb([r, g, b]));
+            outline(img, art, OUTLINE);
+        }
+        if let Some(media) = &self.media {
+            let music = &layout.music;
+            // Text goes right of the art when there is room for art.
+            let (x, y) = if art.width() > 0 {
+                (art.x2 as u32 + 5, art.y as u32 + 5)
+            } else {
+                (music.x as u32 + 2, music.y as u32 + 2)
+            };
+            let pitch = if music.height() >= 60 { 12 } else { 9 };
+            let mut lines = vec![
+                (media.title.as_str(), TEXT),
+                (media.artist.as_str(), DIM_TEXT),
+                (media.album.as_str(), DIM_TEXT),
+            ];
+            if media.is_shuffle {
+                lines.push(("SHUFFLE", DIM_TEXT));
+            }
+            match self.play_state.map(|p| p.repeat) {
+                Some(RepeatMode::Track) => lines.push(("REPEAT ONE", DIM_TEXT)),
+                Some(RepeatMode::Playlist) => lines.push(("REPEAT", DIM_TEXT)),
+                _ => {}
+            }
+            for (i, (s, colour)) in lines.into_iter().enumerate() {
+                line(img, x, y + i as u32 * pitch, music, s, colour, 1);
+            }
+        }
+        // Drawn as of the last update; the firmware would advance it itself.
+        if let Some(timeline) = self.timeline.filter(|t| t.duration_ms > 0) {
+            let music = &layout.music;
+            let track = ScreenSpace {
+                y: music.y2.saturating_sub(PROGRESS_HEIGHT).max(music.y),
+                ..*music
+            };
+            let played = (timeline.position_ms.min(timeline.duration_ms) as u64
+                * track.width() as u64
+                / timeline.duration_ms as u64) as u16;
+            let colour = if timeline.state == PlaybackState::Playing {
+                TEXT
+            } else {
+                DIM_TEXT
+            };
+            fill(img, &track, OUTLINE);
+            fill(
+                img,
+                &ScreenSpace {
+                    x2: track.x + played,
+                    ..track
+                },
+                colour,
+            );
+        }
+
+        // Bars grow up from the bottom of their region.
+        for (space, value, colour) in [
+            (&layout.cpu, self.cpu, CPU_BAR),
+            (&layout.ram, self.ram, RAM_BAR),
+        ] {
+            let top = space.y2 - scale_percent(value, space.height());
+            let bar = ScreenSpace { y: top, ..*space };
+            fill(img, &bar, colour);
+        }
+
+        let ts = &layout.ts;
+        let mut y = ts.y as u32;
+        for talker in &self.talkers {
+            if y + TS_LINE_HEIGHT > ts.y2 as u32 {
+                break;
+            }
+            let dot = ScreenSpace {
+                x: ts.x + 1,
+                y: y as u16 + 2,
+                x2: ts.x + 5,
+                y2: y as u16 + 6,
+            };
+            fill(img, &dot, TALKING);
+            line(img, ts.x as u32 + 8, y, ts, talker, TALKING, 1);
+            y += TS_LINE_HEIGHT;
+        }
+        for message in &self.messages {
+            line(img, ts.x as u32, y, ts, message, TEXT, 1);
+            y += TS_LINE_HEIGHT;
+        }
+
+        if self.self_talking {
+            fill(img, &layout.ts_bubble, TALKING);
+        } else {
+            outline(img, &layout.ts_bubble, OUTLINE);
+        }
+
+        if let Some(clock) = &self.clock {
+            let space = &layout.clock;
+            let scale = if space.height() >= 17 { 2 } else { 1 };
+            let top = (space.height() as u32).saturating_sub(font::GLYPH_HEIGHT * scale) / 2;
+            line(
+                img,
+                space.x as u32 + 2,
+                space.y as u32 + top,
+                space,
+                clock,
+                TEXT,
+                scale,
+            );
+        }
+
+        if let Some((region, message)) = &self.alert {
+            if let Some((_, space)) = region.and_then(|r| layout.regions().get(r as usize).copied())
+            {
+                outline(img, space, ALERT);
+                let inner = ScreenSpace {
+                    x: space.x + 1,
+                    y: space.y + 1,
+                    x2: space.x2.saturating_sub(1),
+                    y2: space.y2.saturating_sub(1),
+                };
+                outline(img, &inner, ALERT);
+            }
+            let banner = ScreenSpace {
+                x: 0,
+                y: layout.height.saturating_sub(BANNER_HEIGHT),
+                x2: layout.width,
+                y2: layout.height,
+            };
+            fill(img, &banner, ALERT);
+            line(img, 2, banner.y as u32 + 2, &banner, message, TEXT, 1);
+        }
+    }
+}
+
+fn put(img: &mut RgbImage, x: u32, y: u32, colour: Rgb<u8>) {
+    if x < img.width() && y < img.height() {
+        img.put_pixel(x, y, colour);
+    }
+}
+
+fn fill(img: &mut RgbImage, space: &ScreenSpace, colour: Rgb<u8>) {
+    for y in space.y..space.y2 {
+        for x in space.x..space.x2 {
+            put(img, x as u32, y as u32, colour);
+        }
+    }
+}
+
+fn outline(img: &mut RgbImage, space: &ScreenSpace, colour: Rgb<u8>) {
+    if space.width() == 0 || space.height() == 0 {
+        return;
+    }
+    let (x, y) = (space.x as u32, space.y as u32);
+    let (x2, y2) = (space.x2 as u32 - 1, space.y2 as u32 - 1);
+    for i in x..=x2 {
+        put(img, i, y, colour);
+        put(img, i, y2, colour);
+    }
+    for j in y..=y2 {
+        put(img, x, j, colour);
+        put(img, x2, j, colour);
+    }
+}
+
+/// Draw `s` at (x, y), scaled up `scale` times.
+fn text(img: &mut RgbImage, x: u32, y: u32, s: &str, colour: Rgb<u8>, scale: u32) {
+    for (i, c) in s.chars().enumerate() {
+        let byte = u8::try_from(c).unwrap_or(b'?');
+        let origin = x + i as u32 * font::ADVANCE * scale;
+        for (row, bits) in font::glyph(byte).iter().enumerate() {
+            for col in 0..font::GLYPH_WIDTH {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        put(
+                            img,
+                            origin + col * scale + dx,
+                            y + row as u32 * scale + dy,
+                            colour,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draw one line of text at (x, y), cut off at the edge of `space` and left
+/// out entirely if it would hang off the bottom.
+fn line(
+    img: &mut RgbImage,
+    x: u32,
+    y: u32,
+    space: &ScreenSpace,
+    s: &str,
+    colour: Rgb<u8>,
+    scale: u32,
+) {
+    if y + font::GLYPH_HEIGHT * scale > space.y2 as u32 {
+        return;
+    }
+    let fits = ((space.x2 as u32).saturating_sub(x) / (font::ADVANCE * scale)) as usize;
+    let s: String = s.chars().take(fits).collect();
+    text(img, x, y, &s, colour, scale);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::alerts::AlertMsg;
+    use crate::background::clock::Time;
+    use crate::background::now_playing::{AlbumArt, MediaInfo};
+    use crate::background::pc_stats::PCStatMsg;
+    use crate::background::ts6::Ts6HidEvent;
+    use crate::types::HidEvent;
+
+    /// Screens are compared against PNGs in `golden/`. Run with
+    /// `UPDATE_GOLDEN=1` to rewrite them after an intended change, and check
+    /// the new images by eye before committing them.
+    fn assert_golden(display: &Display, name: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/emulator/golden")
+            .join(format!("{name}.png"));
+        let rendered = display.render();
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            rendered.save(&path).unwrap();
+            return;
+        }
+        let golden = image::open(&path)
+            .unwrap_or_else(|e| panic!("could not open {}: {e}", path.display()))
+            .to_rgb8();
+        assert!(
+            rendered == golden,
+            "{name} no longer matches {}; rerun with UPDATE_GOLDEN=1 if that is intended",
+            path.display()
+        );
+    }
+
+    fn send(display: &mut Display, seq: u8, event: &dyn HidEvent) {
+        for report in event.chunks(seq, display.layout()) {
+            display.push_report(&report);
+        }
+    }
+
+    #[test]
+    fn default_layout() {
+        let mut display = Display::new();
+        let region = display.layout().album_art;
+        let art_size = (region.width() as u32, region.height() as u32);
+        let events: Vec<Box<dyn HidEvent>> = vec![
+            Box::new(Time {
+                hours: 9,
+                minutes: 41,
+                seconds: 0,
+                year: 126,
+                month: 9,
+                day: 18,
+            }),
+            Box::new(MediaInfo {
+                title: Some("Golden Hour".into()),
+                artist: Some("The Testers".into()),
+                album: None,
+                is_shuffle: Some(true),
+                timeline: Some(Timeline {
+                    state: PlaybackState::Playing,
+                    position_ms: 60_000,
+                    duration_ms: 180_000,
+                }),
+            }),
+            Box::new(AlbumArt::new(Some(vec![(
+                art_size,
+                vec![0x12, 0x34, 0x56],
+            )]))),
+            Box::new(PCStatMsg {
+                cpu_percent: 75.0,
+                ram_used_bytes: 8 << 30,
+                ram_total_bytes: 32 << 30,
+            }),
+            Box::new(Ts6HidEvent {
+                nickname: "alice".into(),
+                message: None,
+                talking: true,
+                show: true,
+                is_self: false,
+            }),
+            Box::new(Ts6HidEvent {
+                nickname: "bob".into(),
+                message: Some("gg".into()),
+                talking: false,
+                show: true,
+                is_self: false,
+            }),
+            Box::new(AlertMsg {
+                region: region_index("CPU"),
+                message: "CPU > 70%".into(),
+            }),
+        ];
+        for (seq, event) in events.iter().enumerate() {
+            send(&mut display, seq as u8, event.as_ref());
+        }
+        assert_golden(&display, "default_layout");
+    }
+}
@@ -7,8 +7,12 @@ pub mod nostd_types;
 #[cfg(feature = "std")]
 pub mod background;
 #[cfg(feature = "std")]
+pub mod codegen;
+#[cfg(feature = "std")]
 pub mod config;
 #[cfg(feature = "std")]
+pub mod emulator;
+#[cfg(feature = "std")]
 pub mod stats;
 #[cfg(feature = "std")]
 pub mod types;
@@ -2,11 +2,23 @@
 #![windows_subsystem = "windows"]
 
 use lazy_static::lazy_static;
-use slipstream::background::{self, hid, pc_stats::PCState, process_watcher};
+use slipstream::background::{
+    self, capture,
+    commands::CommandRouter,
+    hid::{self, SupportedEvents},
+    media_control::{self, MediaAction},
+    pc_stats::PCState,
+    process_watcher,
+    queue::EventQueue,
+    transport::{EmulatorTransport, HidapiTransport, Transport},
+};
+use slipstream::codegen;
 use slipstream::config::Config;
+use slipstream::nostd_types::{DEFAULT_LAYOUT, EventType};
 use slipstream::ui::dialog::show_error_dialog;
 use std::ffi::OsStr;
 use std::fs;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use sysinfo::{ProcessesToUpdate, System};
@@ -17,7 +29,7 @@ use tao::{
 use tokio::sync::Mutex;
 use tray_icon::{
     Icon, TrayIconBuilder,
-    menu::{Menu, MenuEvent, MenuItem},
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
 };
 
 lazy_static! {
@@ -26,12 +38,36 @@ lazy_static! {
         .and_then(|contents| {
             toml::from_str::<Config>(&contents)
                 .map_err(|e| format!("Could not parse config.toml: {e}"))
+        })
+        .and_then(|mut config| {
+            config
+                .resolve_layouts()
+                .map_err(|e| format!("Invalid layout in config.toml: {e}"))?;
+            config
+                .check_alerts()
+                .map_err(|e| format!("Invalid alert in config.toml: {e}"))?;
+            Ok(config)
         });
 }
 
 #[cfg(not(test))]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("gen-header") {
+        // slipstream gen-header [path]
+        let path = args
+            .get(2)
+            .map(String::as_str)
+            .unwrap_or(codegen::HEADER_FILE_NAME);
+        fs::write(path, codegen::c_header())?;
+        println!("Wrote {path}");
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("replay") {
+        return replay(&args[2..]).await;
+    }
+
     let config = match config_res.as_ref() {
         Ok(config) => config,
         Err(e) => {
@@ -52,30 +88,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let ts_api_key = ts_api_key.unwrap();
 
-    let device = match config.devices.first() {
-        Some(dev) => dev,
-        None => {
-            let _ = show_error_dialog(
-                "neelix: No Devices Configured",
-                "config.toml has no [[devices]] entries. Please add one and restart the app.",
+    if config.devices.is_empty() {
+        let _ = show_error_dialog(
+            "neelix: No Devices Configured",
+            "config.toml has no [[devices]] entries. Please add one and restart the app.",
+        );
+        std::process::exit(1);
+    }
+
+    let mut handlers = Vec::new();
+    for device in &config.devices {
+        let unknown = device.unknown_events();
+        if !unknown.is_empty() {
+            eprintln!(
+                "Warning: device {} subscribes to unknown event types {:?}",
+                device.label(),
+                unknown
             );
-            std::process::exit(1);
         }
-    };
-
-    let mut hid = match hid::HidHandler::new(device.vid, device.pid).await {
-        Some(hid) => hid,
-        None => {
-            let _ = show_error_dialog(
-                "neelix: HID Device Not Found",
-                &format!(
-                    "Could not open HID device {:04x}:{:04x}. Is the keyboard plugged in?",
-                    device.vid, device.pid
-                ),
+        // Devices that aren't plugged in yet are picked up once they appear
+        let hid = hid::HidHandler::new(device).await;
+        if !hid.is_connected() {
+            eprintln!(
+                "HID device {} not found, waiting for it to be plugged in",
+                device.label()
             );
-            std::process::exit(1);
         }
-    };
+        handlers.push(hid);
+    }
 
     let expected_processes = config.recognised_processes.clone();
     if expected_processes.is_empty() {
@@ -88,15 +128,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Atomic flag to track if we're shutting down
     let shutting_down = Arc::new(AtomicBool::new(false));
 
+    // What the connected devices can show, filled in as they answer the
+    // handshake
+    let support = Arc::new(SupportedEvents::new());
+
     // Spawn background tasks
     let send_events_1 = send_events.clone();
+    let support_1 = support.clone();
+    // Art is thumbnailed for each region size among the devices that show it
+    let art_sizes = background::now_playing::album_art_sizes(
+        config
+            .devices
+            .iter()
+            .filter(|d| d.subscribes_to(EventType::AlbumArt))
+            .map(|d| &d.resolved_layout),
+    );
+    let art_cache = background::art_cache::ArtCache::new(&config.art_cache, art_sizes);
     let shutting_down_1 = shutting_down.clone();
     tokio::spawn(async move {
-        background::now_playing::poll_now_playing(send_events_1, shutting_down_1).await
+        background::now_playing::poll_now_playing(
+            send_events_1,
+            support_1,
+            art_cache,
+            config.players.clone(),
+            shutting_down_1,
+        )
+        .await
     });
 
     let send_events_2 = send_events.clone();
-    let mut pc_state = PCState::init();
+    let mut pc_state = PCState::init(config);
     let system = Arc::new(Mutex::new(System::new()));
 
     check_if_im_running(system.clone()).await;
@@ -117,19 +178,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         proc_watcher.watch(sys, expected_processes, send_events_3, shutting_down_3);
     }
 
-    // HID event handler
-    let shutting_down_4 = shutting_down.clone();
+    let (send_ts6_commands, recv_ts6_commands) = tokio::sync::mpsc::channel(4);
+    let router = Arc::new(CommandRouter::new(send_ts6_commands));
+
+    // One HID task per device, each with its own coalescing queue
+    let mut device_queues = Vec::new();
+    for (device, hid) in handlers.into_iter().enumerate() {
+        let queue = Arc::new(EventQueue::new());
+        device_queues.push(queue.clone());
+        tokio::spawn(hid.run(
+            queue,
+            router.clone(),
+            support.clone(),
+            device,
+            shutting_down.clone(),
+        ));
+    }
+
+    // Fan every event out to each device's queue. Pushing never blocks, so
+    // the shared channel stays empty and any backlog forms in the queues,
+    // where stale events are replaced by newer ones.
     tokio::spawn(async move {
         while let Some(evt) = recv_events.recv().await {
-            if shutting_down_4.load(Ordering::Relaxed) {
-                break;
-            }
-            if !hid.publish_hid_event(evt).await {
-                // Device is gone and could not be re-opened
-                shutting_down_4.store(true, Ordering::Relaxed);
-                break;
+            for queue in &device_queues {
+                queue.push(evt.clone());
             }
         }
+        for queue in &device_queues {
+            queue.close();
+        }
     });
 
     // TS6 event handler
@@ -137,6 +214,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tokio::spawn(async move {
         background::ts6::poll_teamspeak(
             send_events,
+            recv_ts6_commands,
             shutting_down_5,
             &ts_api_key,
             self_name,
@@ -151,6 +229,58 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// slipstream replay <capture> [--speed <factor>] [--emulate <png> | --device <name>]
+///
+/// Push a capture recorded with a device's `capture` option to the first
+/// configured device (or the one named), or render it with the emulator.
+async fn replay(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage =
+        "usage: slipstream replay <capture> [--speed <factor>] [--emulate <png> | --device <name>]";
+    let path = args.first().ok_or(usage)?;
+    let mut speed = 1.0;
+    let mut emulate = None;
+    let mut device = None;
+    let mut rest = args[1..].iter();
+    while let Some(flag) = rest.next() {
+        let value = rest.next().ok_or(usage)?;
+        match flag.as_str() {
+            "--speed" => speed = value.parse::<f64>().map_err(|_| usage)?,
+            "--emulate" => emulate = Some(value),
+            "--device" => device = Some(value),
+            _ => return Err(usage.into()),
+        }
+    }
+    if !(speed.is_finite() && speed > 0.0) {
+        return Err("--speed must be a positive number".into());
+    }
+
+    let capture = capture::read_capture(Path::new(path))?;
+    let mut transport: Box<dyn Transport> = match emulate {
+        Some(png) => Box::new(EmulatorTransport::new(png, DEFAULT_LAYOUT)),
+        None => {
+            let config = config_res.as_ref().map_err(|e| e.clone())?;
+            let device = match device {
+                Some(name) => config
+                    .devices
+                    .iter()
+                    .find(|d| d.label().eq_ignore_ascii_case(name))
+                    .ok_or_else(|| format!("No device named {name} in config.toml"))?,
+                None => config
+                    .devices
+                    .first()
+                    .ok_or("config.toml has no [[devices]] entries")?,
+            };
+            Box::new(HidapiTransport::new(device))
+        }
+    };
+    if !transport.reopen() {
+        return Err("Could not open the device".into());
+    }
+    println!("Replaying {} reports at {speed}x", capture.len());
+    capture::replay(transport.as_mut(), &capture, speed).await?;
+    Ok(())
+}
+
 async fn check_if_im_running(system: Arc<Mutex<System>>) {
     let mut sys = system.lock().await;
     sys.refresh_processes(ProcessesToUpdate::All, true);
@@ -184,9 +314,24 @@ fn run_event_loop(shutting_down: Arc<AtomicBool>) {
 
     // Create tray menu
     let tray_menu = Menu::new();
+    let media_items = [
+        (
+            MenuItem::new("Play/Pause", true, None),
+            MediaAction::PlayPause,
+        ),
+        (MenuItem::new("Next", true, None), MediaAction::Next),
+        (MenuItem::new("Previous", true, None), MediaAction::Previous),
+    ];
+    for (item, _) in &media_items {
+        tray_menu.append(item).unwrap();
+    }
+    tray_menu.append(&PredefinedMenuItem::separator()).unwrap();
     let quit_item = MenuItem::new("Quit", true, None);
     tray_menu.append(&quit_item).unwrap();
 
+    // The loop runs outside any task, so hand media actions to the runtime
+    let runtime = tokio::runtime::Handle::current();
+
     // Build tray icon
     let _tray_icon = TrayIconBuilder::new()
         .with_menu(Box::new(tray_menu))
@@ -204,12 +349,18 @@ fn run_event_loop(shutting_down: Arc<AtomicBool>) {
             if menu_event.id() == quit_id {
                 shutting_down.store(true, Ordering::Relaxed);
                 *control_flow = ControlFlow::Exit;
+            } else if let Some((_, action)) = media_items
+                .iter()
+                .find(|(item, _)| item.id() == menu_event.id())
+            {
+                let _guard = runtime.enter();
+                media_control::spawn(*action);
             }
         } else if shutting_down.load(Ordering::Relaxed) && *control_flow != ControlFlow::Exit {
             // A background task flagged shutdown without the user asking — surface it
             let _ = show_error_dialog(
                 "Slipstream encountered an unexpected error",
-                "A background task shut down unexpectedly (e.g. the HID device could not be re-opened).",
+                "A background task shut down unexpectedly.",
             );
             *control_flow = ControlFlow::Exit;
         } else {
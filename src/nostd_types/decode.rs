@@ -0,0 +1,781 @@
+//! Zero-allocation decoding of the HID frame stream. Firmware feeds every
+//! 32-byte report into a [`FrameReassembler`] and gets back a borrowed,
+//! typed [`Event`] once a complete, checksummed frame has arrived; host-side
+//! tooling uses the same decoder so both ends agree on the wire format.
+
+use super::{
+    ALERT_NO_REGION, EventType, FrameHeader, Layout, MAX_HID_EVENT_SIZE, PlayState, SPLIT_CHAR,
+    Timeline, is_footer, unpack_rate,
+};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FrameError {
+    /// The advertised payload does not fit in the reassembly buffer.
+    Overflow,
+    /// A header arrived where the previous frame's footer should be.
+    Torn,
+    /// The report after the last payload report was not a footer.
+    MissingFooter,
+    /// Payload length or CRC did not match the header.
+    BadChecksum,
+    /// Same sequence id and checksum as the previous frame.
+    Duplicate,
+}
+
+#[derive(Copy, Clone)]
+enum State {
+    /// Waiting for a header report; anything else is ignored.
+    Idle,
+    /// Copying payload reports into the buffer.
+    Payload {
+        header: FrameHeader,
+        remaining: usize,
+    },
+    /// All payload reports received, the next report must be the footer.
+    Footer { header: FrameHeader },
+    /// Skipping an oversized frame until its footer.
+    Discard,
+}
+
+/// A complete frame whose length and CRC have been checked.
+pub struct Frame<'a> {
+    pub header: FrameHeader,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    pub fn event(&self) -> Option<Event<'a>> {
+        Event::parse(self.header.event_type, self.payload)
+    }
+}
+
+/// Reassembles frames from HID reports into a fixed `N`-byte buffer. `N` only
+/// needs to cover the largest payload the firmware cares about; bigger frames
+/// are skipped with [`FrameError::Overflow`].
+pub struct FrameReassembler<const N: usize> {
+    buf: [u8; N],
+    received: usize,
+    state: State,
+    last: Option<(u8, u16)>,
+}
+
+impl<const N: usize> Default for FrameReassembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FrameReassembler<N> {
+    pub const fn new() -> Self {
+        FrameReassembler {
+            buf: [0; N],
+            received: 0,
+            state: State::Idle,
+            last: None,
+        }
+    }
+
+    /// Forget any partial frame and the duplicate-detection state, e.g. after
+    /// the host reconnects.
+    pub fn reset(&mut self) {
+        self.received = 0;
+        self.state = State::Idle;
+        self.last = None;
+    }
+
+    /// Feed one report. Returns Some once a frame completes (or is rejected),
+    /// None while a frame is still in progress or the report was ignored.
+    pub fn push(&mut self, report: &[u8]) -> Option<Result<Frame<'_>, FrameError>> {
+        // Mid-payload every report is data, even one that starts with
+        // `HEADER` as image bytes can. A real desync shows up as a missing
+        // footer or a bad CRC instead.
+        if let State::Payload { header, remaining } = self.state {
+            let take = (header.len as usize - self.received)
+                .min(MAX_HID_EVENT_SIZE)
+                .min(report.len());
+            self.buf[self.received..self.received + take].copy_from_slice(&report[..take]);
+            self.received += take;
+            self.state = if remaining <= 1 {
+                State::Footer { header }
+            } else {
+                State::Payload {
+                    header,
+                    remaining: remaining - 1,
+                }
+            };
+            return None;
+        }
+
+        if let Some(header) = FrameHeader::parse(report) {
+            let torn = matches!(self.state, State::Footer { .. });
+            self.start(header);
+            // A header with no payload goes straight to waiting for the
+            // footer, so there is nothing to yield yet.
+            return torn.then_some(Err(FrameError::Torn));
+        }
+
+        match self.state {
+            State::Idle | State::Payload { .. } => None,
+            State::Discard => {
+                if is_footer(report) {
+                    self.state = State::Idle;
+                    return Some(Err(FrameError::Overflow));
+                }
+                None
+            }
+            State::Footer { header } => {
+                self.state = State::Idle;
+                if !is_footer(report) {
+                    return Some(Err(FrameError::MissingFooter));
+                }
+                Some(self.complete(header))
+            }
+        }
+    }
+
+    fn start(&mut self, header: FrameHeader) {
+        self.received = 0;
+        self.state = if header.len as usize > N {
+            State::Discard
+        } else if header.payload_reports() == 0 {
+            State::Footer { header }
+        } else {
+            State::Payload {
+                header,
+                remaining: header.payload_reports(),
+            }
+        };
+    }
+
+    fn complete(&mut self, header: FrameHeader) -> Result<Frame<'_>, FrameError> {
+        let payload = &self.buf[..self.received];
+        if !header.validate(payload) {
+            return Err(FrameError::BadChecksum);
+        }
+        if self.last == Some((header.seq, header.crc)) {
+            return Err(FrameError::Duplicate);
+        }
+        self.last = Some((header.seq, header.crc));
+        Ok(Frame { header, payload })
+    }
+}
+
+/// A decoded event, borrowing its text and image data from the frame.
+/// Text fields are the latin-1 bytes the host sent.
+pub enum Event<'a> {
+    Media(MediaView<'a>),
+    Process(ProcessView<'a>),
+    PcStats(PcStatsView),
+    Ts6(Ts6View<'a>),
+    Clock(ClockView),
+    AlbumArt(ImageView<'a>),
+    ProcessIcon(ImageView<'a>),
+    /// Sent as `EventType::RawString`; `None` when the alert has cleared.
+    Alert(Option<AlertView<'a>>),
+    /// The host's protocol version.
+    Hello(u8),
+    Layout(Layout),
+    Sensors(SensorsView<'a>),
+    Throughput(ThroughputView<'a>),
+    CpuHistory(CpuHistoryView<'a>),
+    MediaPosition(Timeline),
+    PlayState(PlayState),
+}
+
+pub struct MediaView<'a> {
+    pub title: &'a [u8],
+    pub artist: &'a [u8],
+    pub album: &'a [u8],
+    pub is_shuffle: Option<bool>,
+    /// Absent from hosts that predate progress bars.
+    pub timeline: Option<Timeline>,
+}
+
+pub struct ProcessView<'a> {
+    pub name: &'a [u8],
+    pub pid: u16,
+    pub is_running: bool,
+    /// `content_hash` of the icon to draw, 0 for none.
+    pub icon_hash: u32,
+}
+
+pub struct PcStatsView {
+    /// Overall CPU usage, in hundredths of a percent (see `PERCENT_MAX`).
+    pub cpu: u16,
+    /// RAM in use, in hundredths of a percent.
+    pub ram: u16,
+    pub ram_used_mib: u32,
+    pub ram_total_mib: u32,
+}
+
+pub struct AlertView<'a> {
+    /// Index into `SCREEN_SPACES` of the region to flash.
+    pub region: Option<u8>,
+    pub message: &'a [u8],
+}
+
+pub struct SensorsView<'a> {
+    pub temperatures: Readings<'a>,
+    pub fans: Readings<'a>,
+}
+
+/// Sensor readings, decoded as they are iterated.
+#[derive(Clone)]
+pub struct Readings<'a> {
+    data: &'a [u8],
+    remaining: u8,
+}
+
+pub struct Reading<'a> {
+    pub label: &'a [u8],
+    raw: u16,
+}
+
+impl Reading<'_> {
+    /// For temperatures, in tenths of a degree Celsius.
+    pub fn celsius_tenths(&self) -> i16 {
+        self.raw as i16
+    }
+
+    /// For fans.
+    pub fn rpm(&self) -> u16 {
+        self.raw
+    }
+}
+
+impl<'a> Readings<'a> {
+    /// Check `count` readings are present at the start of `data`, returning
+    /// them and whatever follows.
+    fn split(data: &'a [u8], count: u8) -> Option<(Self, &'a [u8])> {
+        let mut rest = data;
+        for _ in 0..count {
+            let (_, after) = split_field(rest)?;
+            rest = after.get(2..)?;
+        }
+        let len = data.len() - rest.len();
+        Some((
+            Readings {
+                data: &data[..len],
+                remaining: count,
+            },
+            rest,
+        ))
+    }
+}
+
+pub struct ThroughputView<'a> {
+    /// Bytes per second read from, and written to, all disks.
+    pub disk_read: u64,
+    pub disk_write: u64,
+    pub interfaces: InterfaceRates<'a>,
+}
+
+/// One or more series of CPU usage samples, each a whole percent from 0 to
+/// 100, oldest first. Every series has `len` samples.
+pub struct CpuHistoryView<'a> {
+    pub series_count: u8,
+    pub len: u8,
+    data: &'a [u8],
+}
+
+impl<'a> CpuHistoryView<'a> {
+    /// Series `i`: the busiest core at each sample when there is one series,
+    /// otherwise core `i`.
+    pub fn series(&self, i: u8) -> Option<&'a [u8]> {
+        if i >= self.series_count {
+            return None;
+        }
+        let start = i as usize * self.len as usize;
+        self.data.get(start..start + self.len as usize)
+    }
+}
+
+/// Per-interface network rates, decoded as they are iterated.
+#[derive(Clone)]
+pub struct InterfaceRates<'a> {
+    data: &'a [u8],
+    remaining: u8,
+}
+
+pub struct InterfaceRate<'a> {
+    pub name: &'a [u8],
+    /// Bytes per second received and sent.
+    pub rx: u64,
+    pub tx: u64,
+}
+
+impl<'a> Iterator for InterfaceRates<'a> {
+    type Item = InterfaceRate<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let (name, rest) = split_field(self.data)?;
+        let rates = rest.get(..4)?;
+        self.data = &rest[4..];
+        Some(InterfaceRate {
+            name,
+            rx: unpack_rate(u16::from_le_bytes([rates[0], rates[1]])),
+            tx: unpack_rate(u16::from_le_bytes([rates[2], rates[3]])),
+        })
+    }
+}
+
+impl<'a> Iterator for Readings<'a> {
+    type Item = Reading<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let (label, rest) = split_field(self.data)?;
+        let raw = u16::from_le_bytes([*rest.first()?, *rest.get(1)?]);
+        self.data = &rest[2..];
+        Some(Reading { label, raw })
+    }
+}
+
+pub struct Ts6View<'a> {
+    pub nickname: &'a [u8],
+    pub message: &'a [u8],
+    pub talking: bool,
+    pub show: bool,
+    pub is_self: bool,
+}
+
+pub struct ClockView {
+    /// Years since 1900.
+    pub year: u8,
+    /// Zero-indexed month.
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+pub struct ImageView<'a> {
+    pub hash: u32,
+    /// QGF bytes; empty means clear the image.
+    pub qgf: &'a [u8],
+}
+
+impl<'a> Event<'a> {
+    /// Decode a validated payload. Returns None for unknown event types or a
+    /// payload too short for its type.
+    pub fn parse(event_type: EventType, payload: &'a [u8]) -> Option<Self> {
+        match event_type {
+            EventType::MediaUpdate => {
+                let (title, rest) = split_field(payload)?;
+                let (artist, rest) = split_field(rest)?;
+                let (album, rest) = split_field(rest)?;
+                // shuffle is 0, 1 or empty, so never SPLIT_CHAR
+                let (shuffle, rest) = split_field(rest)?;
+                Some(Event::Media(MediaView {
+                    title,
+                    artist,
+                    album,
+                    is_shuffle: shuffle.first().map(|b| *b != 0),
+                    timeline: Timeline::parse(rest),
+                }))
+            }
+            EventType::ProcessStateUpdate => {
+                let (name, rest) = split_field(payload)?;
+                // pid(2) SPLIT running(1) [SPLIT icon_hash(4)]
+                let pid = u16::from_le_bytes([*rest.first()?, *rest.get(1)?]);
+                let is_running = *rest.get(3)? != 0;
+                let icon_hash = match rest.get(5..9) {
+                    Some(h) => u32::from_le_bytes([h[0], h[1], h[2], h[3]]),
+                    None => 0,
+                };
+                Some(Event::Process(ProcessView {
+                    name,
+                    pid,
+                    is_running,
+                    icon_hash,
+                }))
+            }
+            EventType::PCUpdate => {
+                // cpu(2) SPLIT ram(2) SPLIT used_mib(4) SPLIT total_mib(4) SPLIT
+                let b = payload.get(..16)?;
+                Some(Event::PcStats(PcStatsView {
+                    cpu: u16::from_le_bytes([b[0], b[1]]),
+                    ram: u16::from_le_bytes([b[3], b[4]]),
+                    ram_used_mib: u32::from_le_bytes([b[6], b[7], b[8], b[9]]),
+                    ram_total_mib: u32::from_le_bytes([b[11], b[12], b[13], b[14]]),
+                }))
+            }
+            EventType::TS6 => {
+                // SPLIT talking SPLIT show SPLIT is_self, fixed-width at the end.
+                if payload.len() < 7 {
+                    return None;
+                }
+                let (text, flags) = payload.split_at(payload.len() - 6);
+                let (nickname, rest) = split_field(text)?;
+                let (message, _) = split_field(rest)?;
+                Some(Event::Ts6(Ts6View {
+                    nickname,
+                    message,
+                    talking: flags[1] != 0,
+                    show: flags[3] != 0,
+                    is_self: flags[5] != 0,
+                }))
+            }
+            EventType::Clock => {
+                let b = payload.get(..6)?;
+                Some(Event::Clock(ClockView {
+                    year: b[0],
+                    month: b[1],
+                    day: b[2],
+                    hours: b[3],
+                    minutes: b[4],
+                    seconds: b[5],
+                }))
+            }
+            EventType::AlbumArt => Some(Event::AlbumArt(parse_image(payload)?)),
+            EventType::ProcessIcon => Some(Event::ProcessIcon(parse_image(payload)?)),
+            EventType::RawString => Some(Event::Alert(payload.split_first().map(
+                |(&region, message)| AlertView {
+                    region: (region != ALERT_NO_REGION).then_some(region),
+                    message,
+                },
+            ))),
+            EventType::Hello => Some(Event::Hello(*payload.first()?)),
+            EventType::Layout => Some(Event::Layout(Layout::parse(payload)?)),
+            EventType::Sensors => {
+                // temp_count fan_count, then per reading: label SPLIT value(2)
+                let (temps, rest) = Readings::split(payload.get(2..)?, payload[0])?;
+                let (fans, _) = Readings::split(rest, payload[1])?;
+                Some(Event::Sensors(SensorsView {
+                    temperatures: temps,
+                    fans,
+                }))
+            }
+            EventType::Throughput => {
+                // read(2) write(2) count, then per interface: name SPLIT rx(2) tx(2)
+                let b = payload.get(..5)?;
+                let mut rest = &payload[5..];
+                for _ in 0..b[4] {
+                    let (_, after) = split_field(rest)?;
+                    rest = after.get(4..)?;
+                }
+                Some(Event::Throughput(ThroughputView {
+                    disk_read: unpack_rate(u16::from_le_bytes([b[0], b[1]])),
+                    disk_write: unpack_rate(u16::from_le_bytes([b[2], b[3]])),
+                    interfaces: InterfaceRates {
+                        data: &payload[5..payload.len() - rest.len()],
+                        remaining: b[4],
+                    },
+                }))
+            }
+            EventType::CpuHistory => {
+                // series_count len, then series_count * len samples
+                let (&series_count, rest) = payload.split_first()?;
+                let (&len, rest) = rest.split_first()?;
+                let data = rest.get(..series_count as usize * len as usize)?;
+                Some(Event::CpuHistory(CpuHistoryView {
+                    series_count,
+                    len,
+                    data,
+                }))
+            }
+            EventType::MediaPosition => Some(Event::MediaPosition(Timeline::parse(payload)?)),
+            EventType::MediaUpdateShufflePlay => Some(Event::PlayState(PlayState::parse(payload)?)),
+            EventType::None => None,
+        }
+    }
+}
+
+/// Split off the bytes up to the next SPLIT_CHAR. The final field may be
+/// unterminated.
+fn split_field(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    match data.iter().position(|b| *b == SPLIT_CHAR) {
+        Some(i) => Some((&data[..i], &data[i + 1..])),
+        None => Some((data, &[])),
+    }
+}
+
+/// len(u32 LE) hash(u32 LE) qgf[len]
+fn parse_image(payload: &[u8]) -> Option<ImageView<'_>> {
+    let len = u32::from_le_bytes(payload.get(..4)?.try_into().ok()?) as usize;
+    let hash = u32::from_le_bytes(payload.get(4..8)?.try_into().ok()?);
+    let qgf = payload.get(8..8 + len)?;
+    Some(ImageView { hash, qgf })
+}
+
+// Host-side events are built with the std types, so these only run with the
+// `std` feature.
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::background::alerts::AlertMsg;
+    use crate::background::clock::Time;
+    use crate::background::now_playing::{AlbumArt, MediaInfo};
+    use crate::background::pc_stats::PCStatMsg;
+    use crate::background::process_watcher::Process;
+    use crate::background::ts6::Ts6HidEvent;
+    use crate::nostd_types::{
+        DEFAULT_LAYOUT, HEADER, HidEventImpl, PlayState, PlaybackState, RepeatMode,
+    };
+    use crate::types::{HidEvent, frame};
+
+    /// Feed `event`'s reports through a reassembler and check the frame
+    /// completes on the footer and no sooner.
+    fn round_trip(event: &dyn HidEvent, check: impl FnOnce(Event)) {
+        let mut reassembler = FrameReassembler::<1024>::new();
+        let reports = event.chunks(7, &DEFAULT_LAYOUT);
+        let (footer, rest) = reports.split_last().unwrap();
+        for report in rest {
+            assert!(reassembler.push(report).is_none());
+        }
+        let frame = reassembler.push(footer).unwrap().unwrap();
+        assert_eq!(frame.header.event_type, event.event_type());
+        assert_eq!(frame.header.seq, 7);
+        assert_eq!(frame.payload, event.to_bytes().as_slice());
+        check(frame.event().unwrap());
+    }
+
+    fn push_all<const N: usize>(
+        reassembler: &mut FrameReassembler<N>,
+        reports: &[HidEventImpl],
+    ) -> Vec<Result<EventType, FrameError>> {
+        reports
+            .iter()
+            .filter_map(|r| reassembler.push(r).map(|f| f.map(|f| f.header.event_type)))
+            .collect()
+    }
+
+    #[test]
+    fn media_round_trip() {
+        let timeline = Timeline {
+            state: PlaybackState::Playing,
+            position_ms: 61_000,
+            // 0x0A in the middle of the binary timeline
+            duration_ms: 0x0A0A_0A0A,
+        };
+        let media = MediaInfo {
+            title: Some("A title long enough to need two reports".into()),
+            artist: Some("Artist".into()),
+            album: Some("Album".into()),
+            is_shuffle: Some(true),
+            timeline: Some(timeline),
+        };
+        round_trip(&media, |event| {
+            let Event::Media(m) = event else {
+                panic!("not a media event")
+            };
+            assert_eq!(m.title, b"A title long enough to need two reports");
+            assert_eq!(m.artist, b"Artist");
+            // The album isn't sent
+            assert_eq!(m.album, b"");
+            assert_eq!(m.is_shuffle, Some(true));
+            assert_eq!(m.timeline, Some(timeline));
+        });
+    }
+
+    #[test]
+    fn process_round_trip() {
+        let process = Process {
+            name: "steam.exe".into(),
+            pid: 4321,
+            is_running: true,
+            metadata: None,
+            icon_qgf: Some(vec![1, 2, 3]),
+        };
+        round_trip(&process, |event| {
+            let Event::Process(p) = event else {
+                panic!("not a process event")
+            };
+            assert_eq!(p.name, b"steam.exe");
+            assert_eq!(p.pid, 4321);
+            assert!(p.is_running);
+            assert_eq!(p.icon_hash, crate::nostd_types::content_hash(&[1, 2, 3]));
+        });
+    }
+
+    #[test]
+    fn pc_stats_round_trip() {
+        let stats = PCStatMsg {
+            cpu_percent: 42.5,
+            ram_used_bytes: 4 << 30,
+            ram_total_bytes: 16 << 30,
+        };
+        round_trip(&stats, |event| {
+            let Event::PcStats(s) = event else {
+                panic!("not a PC stats event")
+            };
+            assert_eq!(s.cpu, 4250);
+            assert_eq!(s.ram, 2500);
+            assert_eq!(s.ram_used_mib, 4096);
+            assert_eq!(s.ram_total_mib, 16384);
+        });
+    }
+
+    #[test]
+    fn ts6_round_trip() {
+        let ts = Ts6HidEvent {
+            nickname: "alice".into(),
+            message: Some("hello".into()),
+            talking: false,
+            show: true,
+            is_self: false,
+        };
+        round_trip(&ts, |event| {
+            let Event::Ts6(t) = event else {
+                panic!("not a TS6 event")
+            };
+            assert_eq!(t.nickname, b"alice");
+            assert_eq!(t.message, b"hello");
+            assert!(!t.talking && t.show && !t.is_self);
+        });
+    }
+
+    #[test]
+    fn clock_round_trip() {
+        let time = Time {
+            hours: 23,
+            minutes: 59,
+            seconds: 30,
+            year: 126,
+            month: 9,
+            day: 18,
+        };
+        round_trip(&time, |event| {
+            let Event::Clock(c) = event else {
+                panic!("not a clock event")
+            };
+            assert_eq!((c.hours, c.minutes, c.seconds), (23, 59, 30));
+            assert_eq!((c.year, c.month, c.day), (126, 9, 18));
+        });
+    }
+
+    #[test]
+    fn image_round_trip() {
+        let qgf: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let region = DEFAULT_LAYOUT.album_art;
+        let art_size = (region.width() as u32, region.height() as u32);
+        let art = AlbumArt::new(Some(vec![(art_size, qgf.clone())]));
+        round_trip(&art, |event| {
+            let Event::AlbumArt(image) = event else {
+                panic!("not an album art event")
+            };
+            assert_eq!(image.qgf, qgf.as_slice());
+            assert_eq!(image.hash, crate::nostd_types::content_hash(&qgf));
+        });
+    }
+
+    #[test]
+    fn small_events_round_trip() {
+        let play_state = PlayState {
+            state: PlaybackState::Paused,
+            shuffle: true,
+            repeat: RepeatMode::Playlist,
+        };
+        round_trip(&play_state, |event| {
+            assert!(matches!(event, Event::PlayState(p) if p == play_state));
+        });
+        let alert = AlertMsg {
+            region: Some(3),
+            message: "CPU > 95%".into(),
+        };
+        round_trip(&alert, |event| {
+            let Event::Alert(Some(a)) = event else {
+                panic!("not an alert")
+            };
+            assert_eq!(a.region, Some(3));
+            assert_eq!(a.message, b"CPU > 95%");
+        });
+        round_trip(&AlertMsg::clear(), |event| {
+            assert!(matches!(event, Event::Alert(None)));
+        });
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut reports = frame(EventType::Clock, 1, &[126, 9, 18, 23, 59, 30]);
+        reports[1][2] ^= 0xFF;
+        let mut reassembler = FrameReassembler::<64>::new();
+        assert_eq!(
+            push_all(&mut reassembler, &reports),
+            [Err(FrameError::BadChecksum)]
+        );
+    }
+
+    #[test]
+    fn torn_frame() {
+        let first = frame(EventType::TS6, 1, &[b'x'; 40]);
+        let second = frame(EventType::Clock, 2, &[126, 9, 18, 23, 59, 30]);
+        let third = frame(EventType::Clock, 3, &[126, 9, 18, 23, 59, 31]);
+        let mut reassembler = FrameReassembler::<64>::new();
+        // The first frame loses its footer
+        assert_eq!(push_all(&mut reassembler, &first[..3]), []);
+        assert_eq!(
+            push_all(&mut reassembler, &second),
+            [Err(FrameError::Torn), Ok(EventType::Clock)]
+        );
+        // It loses its last payload report too: the next header is taken
+        // as payload, and that frame is lost along with it
+        assert_eq!(push_all(&mut reassembler, &first[..2]), []);
+        assert_eq!(
+            push_all(&mut reassembler, &second),
+            [Err(FrameError::MissingFooter)]
+        );
+        assert_eq!(push_all(&mut reassembler, &third), [Ok(EventType::Clock)]);
+    }
+
+    #[test]
+    fn payload_that_looks_like_a_header() {
+        // Image bytes can start a report with HEADER and a plausible header
+        let mut qgf = vec![0x55; 32];
+        qgf.extend_from_slice(&HEADER);
+        qgf.extend_from_slice(&[EventType::Clock as u8, 9, 6, 0, 0, 0]);
+        qgf.extend_from_slice(&[0x55; 40]);
+        let reports = frame(EventType::AlbumArt, 1, &qgf);
+        // The header-like bytes open the second payload report
+        assert_eq!(FrameHeader::parse(&reports[2]).map(|h| h.seq), Some(9));
+        let mut reassembler = FrameReassembler::<128>::new();
+        let (last, rest) = reports.split_last().unwrap();
+        for report in rest {
+            assert!(reassembler.push(report).is_none());
+        }
+        let frame = reassembler.push(last).unwrap().unwrap();
+        assert_eq!(frame.header.event_type, EventType::AlbumArt);
+        assert_eq!(frame.payload, qgf.as_slice());
+    }
+
+    #[test]
+    fn out_of_sequence() {
+        let reports = frame(EventType::Clock, 5, &[126, 9, 18, 23, 59, 30]);
+        let mut reassembler = FrameReassembler::<64>::new();
+        // Payload and footer without a header are ignored
+        assert_eq!(push_all(&mut reassembler, &reports[1..]), []);
+        assert_eq!(push_all(&mut reassembler, &reports), [Ok(EventType::Clock)]);
+        // The same frame again is a duplicate
+        assert_eq!(
+            push_all(&mut reassembler, &reports),
+            [Err(FrameError::Duplicate)]
+        );
+        // A payload report where the footer should be
+        let doubled = [reports[0], reports[1], reports[1]];
+        assert_eq!(
+            push_all(&mut reassembler, &doubled),
+            [Err(FrameError::MissingFooter)]
+        );
+        // After a reset the frame is accepted again
+        reassembler.reset();
+        assert_eq!(push_all(&mut reassembler, &reports), [Ok(EventType::Clock)]);
+    }
+
+    #[test]
+    fn overflow() {
+        let reports = frame(EventType::AlbumArt, 1, &[0; 100]);
+        let mut reassembler = FrameReassembler::<64>::new();
+        assert_eq!(
+            push_all(&mut reassembler, &reports),
+            [Err(FrameError::Overflow)]
+        );
+    }
+}
@@ -0,0 +1,796 @@
+pub mod decode;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum EventType {
+    None = 0x0,
+    /// `SPLIT_CHAR`-separated title, artist, album and shuffle flag, then
+    /// after the last split a binary `Timeline` (`Timeline::BYTES_LEN`
+    /// bytes, possibly none). The timeline may itself contain `SPLIT_CHAR`,
+    /// so split off only the first four fields.
+    MediaUpdate = 0x01,
+    /// A `PlayState` on its own: sent when the player starts, pauses or
+    /// stops, or its shuffle or repeat mode changes, without resending the
+    /// track.
+    MediaUpdateShufflePlay = 0x02,
+    ProcessStateUpdate = 0x03,
+    PCUpdate = 0x04,
+    /// The current alert: the region to flash (an index into
+    /// `SCREEN_SPACES`, or `ALERT_NO_REGION`) followed by the message. An
+    /// empty payload clears it.
+    RawString = 0x05,
+    TS6 = 0x06,
+    Clock = 0x07,
+    AlbumArt = 0x08,
+    ProcessIcon = 0x09,
+    /// Sent by the host on connect; payload is `[PROTOCOL_VERSION]`. Firmware
+    /// answers with a `DeviceCommand::Capabilities` report.
+    Hello = 0x0A,
+    /// The device's `Layout`, sent on connect so firmware can place elements
+    /// where the host scaled them for.
+    Layout = 0x0B,
+    /// Temperature and fan readings picked in config.toml.
+    Sensors = 0x0C,
+    /// Network and disk transfer rates, see `pack_rate`.
+    Throughput = 0x0D,
+    /// Recent CPU usage for a history graph.
+    CpuHistory = 0x0E,
+    /// A `Timeline` on its own: sent when playback is paused, resumed or
+    /// seeks, and every few seconds while it plays so a progress bar doesn't
+    /// drift.
+    MediaPosition = 0x0F,
+}
+
+impl EventType {
+    /// Every event type, in wire order. Keep in sync with the enum — the
+    /// generated C header is built from this list.
+    pub const ALL: [EventType; 16] = [
+        EventType::None,
+        EventType::MediaUpdate,
+        EventType::MediaUpdateShufflePlay,
+        EventType::ProcessStateUpdate,
+        EventType::PCUpdate,
+        EventType::RawString,
+        EventType::TS6,
+        EventType::Clock,
+        EventType::AlbumArt,
+        EventType::ProcessIcon,
+        EventType::Hello,
+        EventType::Layout,
+        EventType::Sensors,
+        EventType::Throughput,
+        EventType::CpuHistory,
+        EventType::MediaPosition,
+    ];
+
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0x01 => EventType::MediaUpdate,
+            0x02 => EventType::MediaUpdateShufflePlay,
+            0x03 => EventType::ProcessStateUpdate,
+            0x04 => EventType::PCUpdate,
+            0x05 => EventType::RawString,
+            0x06 => EventType::TS6,
+            0x07 => EventType::Clock,
+            0x08 => EventType::AlbumArt,
+            0x09 => EventType::ProcessIcon,
+            0x0A => EventType::Hello,
+            0x0B => EventType::Layout,
+            0x0C => EventType::Sensors,
+            0x0D => EventType::Throughput,
+            0x0E => EventType::CpuHistory,
+            0x0F => EventType::MediaPosition,
+            _ => EventType::None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.name() == name)
+    }
+
+    /// Stable snake_case name, used for the generated C identifiers and for
+    /// per-device subscriptions in config.toml.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventType::None => "none",
+            EventType::MediaUpdate => "media_update",
+            EventType::MediaUpdateShufflePlay => "media_update_shuffle_play",
+            EventType::ProcessStateUpdate => "process_state_update",
+            EventType::PCUpdate => "pc_update",
+            EventType::RawString => "raw_string",
+            EventType::TS6 => "ts6",
+            EventType::Clock => "clock",
+            EventType::AlbumArt => "album_art",
+            EventType::ProcessIcon => "process_icon",
+            EventType::Hello => "hello",
+            EventType::Layout => "layout",
+            EventType::Sensors => "sensors",
+            EventType::Throughput => "throughput",
+            EventType::CpuHistory => "cpu_history",
+            EventType::MediaPosition => "media_position",
+        }
+    }
+
+    /// This type's bit in a `Capabilities::supported` mask.
+    pub fn bit(&self) -> u32 {
+        1 << *self as u8
+    }
+
+    /// The first protocol version with this type's current payload. Firmware
+    /// on an older version expects a different layout, so it isn't sent the
+    /// type even if it claims to handle it.
+    pub fn min_version(&self) -> u8 {
+        match self {
+            EventType::MediaUpdate
+            | EventType::MediaUpdateShufflePlay
+            | EventType::PCUpdate
+            | EventType::RawString => 2,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ScreenSpace {
+    pub x: u16,
+    pub y: u16,
+    pub x2: u16,
+    pub y2: u16,
+}
+
+impl ScreenSpace {
+    /// A region that isn't shown.
+    pub const EMPTY: ScreenSpace = ScreenSpace {
+        x: 0,
+        y: 0,
+        x2: 0,
+        y2: 0,
+    };
+
+    pub fn width(&self) -> u16 {
+        self.x2.saturating_sub(self.x)
+    }
+
+    pub fn height(&self) -> u16 {
+        self.y2.saturating_sub(self.y)
+    }
+}
+
+pub const MAX_HID_EVENT_SIZE: usize = 32;
+pub type HidEventImpl = [u8; MAX_HID_EVENT_SIZE];
+
+// Every event is sent as one header report, ceil(len / 32) payload reports
+// (the last one zero-padded) and one footer report. The header report is laid
+// out as:
+//
+//   [HEADER; 3] [type] [seq] [len: u16 LE] [crc16(payload): u16 LE]
+//
+// `seq` increments (wrapping) with every frame sent to a device, so firmware
+// can spot dropped or duplicated frames; `len` and the CRC let it discard a
+// torn or corrupt payload instead of drawing it.
+pub const HEADER: [u8; 3] = [0xFA, 0x00, 0xF0];
+pub const FOOTER: [u8; 4] = [0xAF, 0x00, 0x0F, 0x00];
+pub const TYPE_BIT: usize = 3;
+pub const SEQ_BIT: usize = 4;
+pub const LEN_BIT: usize = 5;
+pub const CRC_BIT: usize = 7;
+pub const HEADER_LEN: usize = 9;
+
+pub const CLOCK: ScreenSpace = ScreenSpace {
+    x: 30,
+    y: 220,
+    x2: 320,
+    y2: 240,
+};
+
+pub const MUSIC: ScreenSpace = ScreenSpace {
+    x: 0,
+    y: 0,
+    x2: 300,
+    y2: 80,
+};
+
+/// Album art is drawn on the left of the MUSIC region, vertically centred.
+pub const ALBUM_ART: ScreenSpace = ScreenSpace {
+    x: 0,
+    y: 15,
+    x2: 50,
+    y2: 65,
+};
+
+pub const CPU: ScreenSpace = ScreenSpace {
+    x: 305,
+    y: 0,
+    x2: 310,
+    y2: 240,
+};
+pub const RAM: ScreenSpace = ScreenSpace {
+    x: 312,
+    y: 0,
+    x2: 317,
+    y2: 240,
+};
+
+pub const TS: ScreenSpace = ScreenSpace {
+    x: 0,
+    y: 110,
+    x2: 300,
+    y2: 220,
+};
+
+pub const TS_BUBBLE: ScreenSpace = ScreenSpace {
+    x: 275,
+    y: 229,
+    x2: 285,
+    y2: 240,
+};
+
+/// Every named screen region, for the generated C header.
+pub const SCREEN_SPACES: [(&str, &ScreenSpace); 7] = [
+    ("CLOCK", &CLOCK),
+    ("MUSIC", &MUSIC),
+    ("ALBUM_ART", &ALBUM_ART),
+    ("CPU", &CPU),
+    ("RAM", &RAM),
+    ("TS", &TS),
+    ("TS_BUBBLE", &TS_BUBBLE),
+];
+
+/// Index of the region called `name` (case-insensitive) in `SCREEN_SPACES`.
+pub fn region_index(name: &str) -> Option<u8> {
+    SCREEN_SPACES
+        .iter()
+        .position(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|i| i as u8)
+}
+
+/// Region byte of an alert that doesn't flash anything.
+pub const ALERT_NO_REGION: u8 = 0xFF;
+
+/// Where everything goes on a device's screen. The constants above are the
+/// layout of the original 320x240 display; other displays get their own
+/// layout from config.toml. Regions a layout doesn't use are
+/// `ScreenSpace::EMPTY`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Layout {
+    pub width: u16,
+    pub height: u16,
+    pub clock: ScreenSpace,
+    pub music: ScreenSpace,
+    pub album_art: ScreenSpace,
+    pub cpu: ScreenSpace,
+    pub ram: ScreenSpace,
+    pub ts: ScreenSpace,
+    pub ts_bubble: ScreenSpace,
+}
+
+pub const DEFAULT_LAYOUT: Layout = Layout {
+    width: 320,
+    height: 240,
+    clock: CLOCK,
+    music: MUSIC,
+    album_art: ALBUM_ART,
+    cpu: CPU,
+    ram: RAM,
+    ts: TS,
+    ts_bubble: TS_BUBBLE,
+};
+
+impl Default for Layout {
+    fn default() -> Self {
+        DEFAULT_LAYOUT
+    }
+}
+
+// A Layout event is the screen size followed by every region in
+// `SCREEN_SPACES` order, all u16 LE:
+//
+//   [width] [height] ([x] [y] [x2] [y2]) * 7
+impl Layout {
+    pub const BYTES_LEN: usize = 4 + SCREEN_SPACES.len() * 8;
+
+    /// Every region with its `SCREEN_SPACES` name, in the same order.
+    pub fn regions(&self) -> [(&'static str, &ScreenSpace); 7] {
+        [
+            ("CLOCK", &self.clock),
+            ("MUSIC", &self.music),
+            ("ALBUM_ART", &self.album_art),
+            ("CPU", &self.cpu),
+            ("RAM", &self.ram),
+            ("TS", &self.ts),
+            ("TS_BUBBLE", &self.ts_bubble),
+        ]
+    }
+
+    fn regions_mut(&mut self) -> [&mut ScreenSpace; 7] {
+        [
+            &mut self.clock,
+            &mut self.music,
+            &mut self.album_art,
+            &mut self.cpu,
+            &mut self.ram,
+            &mut self.ts,
+            &mut self.ts_bubble,
+        ]
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::BYTES_LEN] {
+        let mut bytes = [0u8; Self::BYTES_LEN];
+        bytes[0..2].copy_from_slice(&self.width.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.height.to_le_bytes());
+        for (i, (_, space)) in self.regions().iter().enumerate() {
+            let at = 4 + i * 8;
+            for (j, v) in [space.x, space.y, space.x2, space.y2].iter().enumerate() {
+                bytes[at + j * 2..at + j * 2 + 2].copy_from_slice(&v.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let bytes = payload.get(..Self::BYTES_LEN)?;
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let mut layout = Layout {
+            width: u16_at(0),
+            height: u16_at(2),
+            ..DEFAULT_LAYOUT
+        };
+        for (i, space) in layout.regions_mut().into_iter().enumerate() {
+            let at = 4 + i * 8;
+            *space = ScreenSpace {
+                x: u16_at(at),
+                y: u16_at(at + 2),
+                x2: u16_at(at + 4),
+                y2: u16_at(at + 6),
+            };
+        }
+        Some(layout)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PlaybackState {
+    Stopped = 0,
+    Playing = 1,
+    Paused = 2,
+}
+
+impl PlaybackState {
+    /// Every state, in wire order. Keep in sync with the enum.
+    pub const ALL: [PlaybackState; 3] = [
+        PlaybackState::Stopped,
+        PlaybackState::Playing,
+        PlaybackState::Paused,
+    ];
+
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => PlaybackState::Playing,
+            2 => PlaybackState::Paused,
+            _ => PlaybackState::Stopped,
+        }
+    }
+
+    /// Stable snake_case name, used for the generated C identifiers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlaybackState::Stopped => "stopped",
+            PlaybackState::Playing => "playing",
+            PlaybackState::Paused => "paused",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RepeatMode {
+    Off = 0,
+    /// The current track starts again when it ends.
+    Track = 1,
+    /// The playlist starts again when it ends.
+    Playlist = 2,
+}
+
+impl RepeatMode {
+    /// Every mode, in wire order. Keep in sync with the enum.
+    pub const ALL: [RepeatMode; 3] = [RepeatMode::Off, RepeatMode::Track, RepeatMode::Playlist];
+
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => RepeatMode::Track,
+            2 => RepeatMode::Playlist,
+            _ => RepeatMode::Off,
+        }
+    }
+
+    /// Stable snake_case name, used for the generated C identifiers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RepeatMode::Off => "off",
+            RepeatMode::Track => "track",
+            RepeatMode::Playlist => "playlist",
+        }
+    }
+}
+
+/// The player's modes, sent as `MediaUpdateShufflePlay`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PlayState {
+    pub state: PlaybackState,
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+}
+
+//   [state] [shuffle] [repeat]
+impl PlayState {
+    pub const BYTES_LEN: usize = 3;
+
+    pub fn to_bytes(&self) -> [u8; Self::BYTES_LEN] {
+        [self.state as u8, self.shuffle as u8, self.repeat as u8]
+    }
+
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let bytes = payload.get(..Self::BYTES_LEN)?;
+        Some(PlayState {
+            state: PlaybackState::from_u8(bytes[0]),
+            shuffle: bytes[1] != 0,
+            repeat: RepeatMode::from_u8(bytes[2]),
+        })
+    }
+}
+
+/// Where playback is, for drawing a progress bar. Sent at the end of a media
+/// update and on its own as `MediaPosition`. While playing, firmware should
+/// advance the position itself between updates.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Timeline {
+    pub state: PlaybackState,
+    pub position_ms: u32,
+    /// 0 when the length isn't known, e.g. for live streams.
+    pub duration_ms: u32,
+}
+
+//   [state] [position_ms u32 LE] [duration_ms u32 LE]
+impl Timeline {
+    pub const BYTES_LEN: usize = 9;
+
+    /// Where playback will be `elapsed_ms` after this was read, stopping at
+    /// the end of the track.
+    pub fn advanced(&self, elapsed_ms: u32) -> Self {
+        if self.state != PlaybackState::Playing {
+            return *self;
+        }
+        let mut position_ms = self.position_ms.saturating_add(elapsed_ms);
+        if self.duration_ms > 0 {
+            position_ms = position_ms.min(self.duration_ms);
+        }
+        Timeline {
+            position_ms,
+            ..*self
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::BYTES_LEN] {
+        let mut bytes = [0u8; Self::BYTES_LEN];
+        bytes[0] = self.state as u8;
+        bytes[1..5].copy_from_slice(&self.position_ms.to_le_bytes());
+        bytes[5..9].copy_from_slice(&self.duration_ms.to_le_bytes());
+        bytes
+    }
+
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let bytes = payload.get(..Self::BYTES_LEN)?;
+        Some(Timeline {
+            state: PlaybackState::from_u8(bytes[0]),
+            position_ms: u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+            duration_ms: u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]),
+        })
+    }
+}
+
+pub const SPLIT_CHAR: u8 = '\n' as u8;
+
+/// Percentages are sent as fixed-point hundredths of a percent, so this is
+/// 100.00%.
+pub const PERCENT_MAX: u16 = 10_000;
+
+/// Most temperatures, and separately most fans, in one Sensors event.
+pub const MAX_SENSOR_READINGS: usize = 4;
+
+/// Most network interfaces in one Throughput event.
+pub const MAX_NET_INTERFACES: usize = 4;
+
+/// Most samples per series, and most series (cores), in one CpuHistory
+/// event.
+pub const MAX_CPU_HISTORY_LEN: usize = 64;
+pub const MAX_CPU_HISTORY_SERIES: usize = 16;
+
+/// Bits of a packed rate holding the value; the two above it are the unit.
+pub const RATE_VALUE_BITS: u32 = 14;
+const RATE_VALUE_MASK: u64 = (1 << RATE_VALUE_BITS) - 1;
+
+/// Pack a rate in bytes per second into two bytes: a 14-bit value in B/s,
+/// KiB/s, MiB/s or GiB/s (unit 0 to 3 in the top bits), using the smallest
+/// unit it fits. Good to about 1/16th, which is plenty for a glance.
+pub fn pack_rate(bytes_per_sec: u64) -> u16 {
+    let mut value = bytes_per_sec;
+    let mut unit = 0;
+    while value > RATE_VALUE_MASK && unit < 3 {
+        value >>= 10;
+        unit += 1;
+    }
+    ((unit << RATE_VALUE_BITS) | value.min(RATE_VALUE_MASK)) as u16
+}
+
+/// Bytes per second from a `pack_rate` value.
+pub fn unpack_rate(packed: u16) -> u64 {
+    let unit = packed >> RATE_VALUE_BITS;
+    (packed as u64 & RATE_VALUE_MASK) << (10 * unit)
+}
+
+/// Scale a fixed-point percentage to a length of `pixels`, e.g. a bar height.
+pub fn scale_percent(percent: u16, pixels: u16) -> u16 {
+    (percent.min(PERCENT_MAX) as u32 * pixels as u32 / PERCENT_MAX as u32) as u16
+}
+
+/// FNV-1a over `data`. Used to identify QGF images so the host can skip
+/// re-sending art the device is already showing, and so firmware can verify a
+/// reassembled transfer.
+pub fn content_hash(data: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in data {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF) over a frame payload.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The decoded header report of a frame.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FrameHeader {
+    pub event_type: EventType,
+    pub seq: u8,
+    pub len: u16,
+    pub crc: u16,
+}
+
+impl FrameHeader {
+    pub fn new(event_type: EventType, seq: u8, payload: &[u8]) -> Self {
+        FrameHeader {
+            event_type,
+            seq,
+            len: payload.len() as u16,
+            crc: crc16(payload),
+        }
+    }
+
+    /// Parse a header report. Returns None when the report does not start
+    /// with `HEADER`.
+    pub fn parse(report: &[u8]) -> Option<Self> {
+        if report.len() < HEADER_LEN || report[..HEADER.len()] != HEADER {
+            return None;
+        }
+        Some(FrameHeader {
+            event_type: EventType::from_u8(report[TYPE_BIT]),
+            seq: report[SEQ_BIT],
+            len: u16::from_le_bytes([report[LEN_BIT], report[LEN_BIT + 1]]),
+            crc: u16::from_le_bytes([report[CRC_BIT], report[CRC_BIT + 1]]),
+        })
+    }
+
+    pub fn to_report(&self) -> HidEventImpl {
+        let mut report = [0u8; MAX_HID_EVENT_SIZE];
+        report[..HEADER.len()].copy_from_slice(&HEADER);
+        report[TYPE_BIT] = self.event_type as u8;
+        report[SEQ_BIT] = self.seq;
+        report[LEN_BIT..LEN_BIT + 2].copy_from_slice(&self.len.to_le_bytes());
+        report[CRC_BIT..CRC_BIT + 2].copy_from_slice(&self.crc.to_le_bytes());
+        report
+    }
+
+    /// Number of payload reports between this header and the footer.
+    pub fn payload_reports(&self) -> usize {
+        (self.len as usize).div_ceil(MAX_HID_EVENT_SIZE)
+    }
+
+    /// True when `payload` (with any report padding already stripped) has the
+    /// advertised length and checksum.
+    pub fn validate(&self, payload: &[u8]) -> bool {
+        payload.len() == self.len as usize && crc16(payload) == self.crc
+    }
+}
+
+pub fn is_footer(report: &[u8]) -> bool {
+    report.len() >= FOOTER.len() && report[..FOOTER.len()] == FOOTER
+}
+
+// Commands travel the other way, keyboard to host, as a single input report:
+//
+//   [COMMAND_HEADER; 3] [command] [args...]
+pub const COMMAND_HEADER: [u8; 3] = [0xFC, 0x00, 0xC0];
+pub const COMMAND_BIT: usize = 3;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeviceCommand {
+    None = 0x0,
+    MediaPlayPause = 0x01,
+    MediaNext = 0x02,
+    MediaPrevious = 0x03,
+    Ts6ToggleMute = 0x04,
+    /// Ask the host to resend its current state, e.g. after a firmware reset.
+    RequestRefresh = 0x05,
+    /// Reply to `EventType::Hello`; the arguments are a `Capabilities`.
+    Capabilities = 0x06,
+}
+
+impl DeviceCommand {
+    /// Every command, in wire order. Keep in sync with the enum.
+    pub const ALL: [DeviceCommand; 7] = [
+        DeviceCommand::None,
+        DeviceCommand::MediaPlayPause,
+        DeviceCommand::MediaNext,
+        DeviceCommand::MediaPrevious,
+        DeviceCommand::Ts6ToggleMute,
+        DeviceCommand::RequestRefresh,
+        DeviceCommand::Capabilities,
+    ];
+
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0x01 => DeviceCommand::MediaPlayPause,
+            0x02 => DeviceCommand::MediaNext,
+            0x03 => DeviceCommand::MediaPrevious,
+            0x04 => DeviceCommand::Ts6ToggleMute,
+            0x05 => DeviceCommand::RequestRefresh,
+            0x06 => DeviceCommand::Capabilities,
+            _ => DeviceCommand::None,
+        }
+    }
+
+    /// Stable snake_case name, used for the generated C identifiers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceCommand::None => "none",
+            DeviceCommand::MediaPlayPause => "media_play_pause",
+            DeviceCommand::MediaNext => "media_next",
+            DeviceCommand::MediaPrevious => "media_previous",
+            DeviceCommand::Ts6ToggleMute => "ts6_toggle_mute",
+            DeviceCommand::RequestRefresh => "request_refresh",
+            DeviceCommand::Capabilities => "capabilities",
+        }
+    }
+
+    /// Parse an input report, returning the command and its argument bytes.
+    pub fn parse(report: &[u8]) -> Option<(Self, &[u8])> {
+        if report.len() <= COMMAND_BIT || report[..COMMAND_HEADER.len()] != COMMAND_HEADER {
+            return None;
+        }
+        match DeviceCommand::from_u8(report[COMMAND_BIT]) {
+            DeviceCommand::None => None,
+            cmd => Some((cmd, &report[COMMAND_BIT + 1..])),
+        }
+    }
+
+    /// Build the input report for this command (firmware side).
+    pub fn to_report(&self, args: &[u8]) -> HidEventImpl {
+        let mut report = [0u8; MAX_HID_EVENT_SIZE];
+        report[..COMMAND_HEADER.len()].copy_from_slice(&COMMAND_HEADER);
+        report[COMMAND_BIT] = *self as u8;
+        let n = args.len().min(MAX_HID_EVENT_SIZE - COMMAND_BIT - 1);
+        report[COMMAND_BIT + 1..COMMAND_BIT + 1 + n].copy_from_slice(&args[..n]);
+        report
+    }
+}
+
+/// Version of the frame format and event payloads in this module, announced
+/// in `EventType::Hello`. Bump it on any incompatible change, and raise
+/// `EventType::min_version` for the types whose payload changed.
+///
+/// - 1: the handshake, framed events with sequence ids and CRCs.
+/// - 2: `MediaUpdate` ends with a binary `Timeline`, and
+///   `MediaUpdateShufflePlay` carries a whole `PlayState`. `PCUpdate` sends
+///   fixed-point percents rather than pixel heights. `RawString` starts
+///   with a region byte, and an empty one clears it.
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// What the firmware can do, sent as the arguments of a
+/// `DeviceCommand::Capabilities` report:
+///
+///   [version] [supported: u32 LE] [width: u16 LE] [height: u16 LE] [max_report_size]
+///
+/// Bit n of `supported` is set when the firmware handles `EventType` n.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Capabilities {
+    pub version: u8,
+    pub supported: u32,
+    pub width: u16,
+    pub height: u16,
+    pub max_report_size: u8,
+}
+
+impl Capabilities {
+    pub const ARGS_LEN: usize = 10;
+
+    /// Assumed for firmware that predates the handshake and never answers a
+    /// Hello: the original event types whose payloads haven't changed since,
+    /// on the original screen.
+    pub const LEGACY: Capabilities = Capabilities {
+        version: 0,
+        supported: (1 << EventType::ProcessStateUpdate as u8)
+            | (1 << EventType::TS6 as u8)
+            | (1 << EventType::Clock as u8),
+        width: DEFAULT_LAYOUT.width,
+        height: DEFAULT_LAYOUT.height,
+        max_report_size: MAX_HID_EVENT_SIZE as u8,
+    };
+
+    pub fn supports(&self, event_type: EventType) -> bool {
+        self.supported & event_type.bit() != 0 && self.version >= event_type.min_version()
+    }
+
+    pub fn parse(args: &[u8]) -> Option<Self> {
+        let args = args.get(..Self::ARGS_LEN)?;
+        Some(Capabilities {
+            version: args[0],
+            supported: u32::from_le_bytes([args[1], args[2], args[3], args[4]]),
+            width: u16::from_le_bytes([args[5], args[6]]),
+            height: u16::from_le_bytes([args[7], args[8]]),
+            max_report_size: args[9],
+        })
+    }
+
+    /// The argument bytes for a `DeviceCommand::Capabilities` report
+    /// (firmware side).
+    pub fn to_args(&self) -> [u8; Self::ARGS_LEN] {
+        let mut args = [0u8; Self::ARGS_LEN];
+        args[0] = self.version;
+        args[1..5].copy_from_slice(&self.supported.to_le_bytes());
+        args[5..7].copy_from_slice(&self.width.to_le_bytes());
+        args[7..9].copy_from_slice(&self.height.to_le_bytes());
+        args[9] = self.max_report_size;
+        args
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    /// `all` must hold exactly the values `from_u8` decodes, in wire order,
+    /// or the generated C header drifts from the enum.
+    fn assert_all_decodable<T: Copy + PartialEq + core::fmt::Debug>(
+        all: &[T],
+        from_u8: fn(u8) -> T,
+        to_u8: fn(T) -> u8,
+    ) {
+        let decoded: Vec<T> = (0..=u8::MAX)
+            .map(from_u8)
+            .enumerate()
+            .filter(|(value, t)| to_u8(*t) as usize == *value)
+            .map(|(_, t)| t)
+            .collect();
+        assert_eq!(all, decoded.as_slice());
+    }
+
+    #[test]
+    fn all_lists_match_from_u8() {
+        assert_all_decodable(&EventType::ALL, EventType::from_u8, |t| t as u8);
+        assert_all_decodable(&PlaybackState::ALL, PlaybackState::from_u8, |t| t as u8);
+        assert_all_decodable(&RepeatMode::ALL, RepeatMode::from_u8, |t| t as u8);
+        assert_all_decodable(&DeviceCommand::ALL, DeviceCommand::from_u8, |t| t as u8);
+    }
+}
@@ -1,3 +1,300 @@
-// PC stats
-// TEMP, util, ram, FPS if poss
-// Maybe even nice graphics
\ No newline at end of file
+//! Temperature and fan sensors, and network and disk throughput.
+//! Temperatures come from sysinfo's `Components` on every platform; fan
+//! speeds are read from hwmon, so they are only available on Linux.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::time::Instant;
+use sysinfo::{Components, DiskRefreshKind, Disks, Networks};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub label: String,
+    /// Degrees Celsius for temperatures, RPM for fans.
+    pub value: f32,
+}
+
+pub struct Sensors {
+    components: Components,
+}
+
+impl Default for Sensors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sensors {
+    pub fn new() -> Self {
+        Sensors {
+            components: Components::new_with_refreshed_list(),
+        }
+    }
+
+    pub fn refresh(&mut self) {
+        self.components.refresh(true);
+    }
+
+    /// Every temperature sensor with a current reading.
+    pub fn temperatures(&self) -> Vec<Reading> {
+        self.components
+            .list()
+            .iter()
+            .filter_map(|c| {
+                Some(Reading {
+                    label: c.label().to_string(),
+                    value: c.temperature()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Every fan that reports its speed.
+    pub fn fans(&self) -> Vec<Reading> {
+        hwmon_fans()
+    }
+}
+
+/// Fans under /sys/class/hwmon, labelled by `fanN_label` where the driver
+/// provides one and "<chip> fanN" otherwise.
+#[cfg(target_os = "linux")]
+fn hwmon_fans() -> Vec<Reading> {
+    use std::fs;
+
+    let mut fans = Vec::new();
+    let Ok(chips) = fs::read_dir("/sys/class/hwmon") else {
+        return fans;
+    };
+    let mut chips: Vec<_> = chips.flatten().map(|c| c.path()).collect();
+    chips.sort();
+    for chip in chips {
+        let name = fs::read_to_string(chip.join("name")).unwrap_or_default();
+        let name = name.trim();
+        let Ok(files) = fs::read_dir(&chip) else {
+            continue;
+        };
+        let mut inputs: Vec<String> = files
+            .flatten()
+            .filter_map(|f| f.file_name().into_string().ok())
+            .filter(|f| f.starts_with("fan") && f.ends_with("_input"))
+            .collect();
+        inputs.sort();
+        for input in inputs {
+            let fan = input.trim_end_matches("_input");
+            let Some(rpm) = fs::read_to_string(chip.join(&input))
+                .ok()
+                .and_then(|s| s.trim().parse::<f32>().ok())
+            else {
+                continue;
+            };
+            let label = match fs::read_to_string(chip.join(format!("{fan}_label"))) {
+                Ok(label) => label.trim().to_string(),
+                Err(_) => format!("{name} {fan}"),
+            };
+            fans.push(Reading { label, value: rpm });
+        }
+    }
+    fans
+}
+
+#[cfg(not(target_os = "linux"))]
+fn hwmon_fans() -> Vec<Reading> {
+    Vec::new()
+}
+
+/// Pick the readings named in `labels`, in that order, matching each as a
+/// case-insensitive substring of the sensor label. Without `labels`, the
+/// first `max` readings.
+pub fn select(readings: &[Reading], labels: Option<&[String]>, max: usize) -> Vec<Reading> {
+    let Some(labels) = labels else {
+        return readings.iter().take(max).cloned().collect();
+    };
+    labels
+        .iter()
+        .filter_map(|wanted| {
+            let wanted = wanted.to_lowercase();
+            readings
+                .iter()
+                .find(|r| r.label.to_lowercase().contains(&wanted))
+                .cloned()
+        })
+        .take(max)
+        .collect()
+}
+
+/// Bytes per second through one network interface.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceRate {
+    pub name: String,
+    pub rx: u64,
+    pub tx: u64,
+}
+
+/// Transfer rates averaged over the time since the previous sample.
+#[derive(Debug, Clone, Default)]
+pub struct ThroughputSample {
+    /// Every interface that has carried traffic, loopback excluded, by name.
+    pub interfaces: Vec<InterfaceRate>,
+    /// Bytes per second over all disks.
+    pub disk_read: u64,
+    pub disk_write: u64,
+}
+
+pub struct Throughput {
+    networks: Networks,
+    disks: Disks,
+    /// Received and transmitted totals by interface at the last sample.
+    last_net: HashMap<String, (u64, u64)>,
+    /// Read and written totals by disk device at the last sample.
+    last_disk: HashMap<OsString, (u64, u64)>,
+    last_at: Instant,
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        let mut throughput = Throughput {
+            networks: Networks::new_with_refreshed_list(),
+            disks: Disks::new_with_refreshed_list_specifics(
+                DiskRefreshKind::nothing().with_io_usage(),
+            ),
+            last_net: HashMap::new(),
+            last_disk: HashMap::new(),
+            last_at: Instant::now(),
+        };
+        // Take the starting totals so the first sample is a real delta
+        throughput.sample();
+        throughput
+    }
+
+    /// Rates since the last call. Rates are worked out from the running
+    /// totals so a slow poll doesn't read as a spike.
+    pub fn sample(&mut self) -> ThroughputSample {
+        self.networks.refresh(true);
+        self.disks
+            .refresh_specifics(true, DiskRefreshKind::nothing().with_io_usage());
+        let now = Instant::now();
+        let secs = now.duration_since(self.last_at).as_secs_f64();
+        self.last_at = now;
+        let rate = |now: u64, before: u64| per_sec(now, before, secs);
+
+        let mut interfaces = Vec::new();
+        let mut totals = HashMap::new();
+        for (name, data) in self.networks.list() {
+            let total = (data.total_received(), data.total_transmitted());
+            totals.insert(name.clone(), total);
+            if is_loopback(name) || total == (0, 0) {
+                continue;
+            }
+            // Interfaces that just appeared start from their current totals
+            let before = self.last_net.get(name).copied().unwrap_or(total);
+            interfaces.push(InterfaceRate {
+                name: name.clone(),
+                rx: rate(total.0, before.0),
+                tx: rate(total.1, before.1),
+            });
+        }
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        self.last_net = totals;
+
+        // Several mounts can share a device, so count each device once
+        let mut devices = HashMap::new();
+        for disk in self.disks.list() {
+            let usage = disk.usage();
+            devices.insert(
+                disk.name().to_os_string(),
+                (usage.total_read_bytes, usage.total_written_bytes),
+            );
+        }
+        let (disk_read, disk_write) = disk_rates(&devices, &self.last_disk, secs);
+        self.last_disk = devices;
+        ThroughputSample {
+            interfaces,
+            disk_read,
+            disk_write,
+        }
+    }
+}
+
+/// Bytes per second between two running totals taken `secs` apart.
+fn per_sec(now: u64, before: u64, secs: f64) -> u64 {
+    if secs > 0.0 {
+        (now.saturating_sub(before) as f64 / secs) as u64
+    } else {
+        0
+    }
+}
+
+/// Read and write rates summed over every device. Each device is compared
+/// with its own last totals, so one appearing or going away doesn't show up
+/// as a burst of traffic; new devices start from their current totals.
+fn disk_rates(
+    devices: &HashMap<OsString, (u64, u64)>,
+    last: &HashMap<OsString, (u64, u64)>,
+    secs: f64,
+) -> (u64, u64) {
+    devices.iter().fold((0, 0), |(r, w), (name, &total)| {
+        let before = last.get(name).copied().unwrap_or(total);
+        (
+            r + per_sec(total.0, before.0, secs),
+            w + per_sec(total.1, before.1, secs),
+        )
+    })
+}
+
+fn is_loopback(name: &str) -> bool {
+    name == "lo" || name.to_lowercase().contains("loopback")
+}
+
+/// Pick the interfaces named in `names`, in that order. Without `names`, the
+/// first `max` interfaces.
+pub fn select_interfaces(
+    interfaces: &[InterfaceRate],
+    names: Option<&[String]>,
+    max: usize,
+) -> Vec<InterfaceRate> {
+    let Some(names) = names else {
+        return interfaces.iter().take(max).cloned().collect();
+    };
+    names
+        .iter()
+        .filter_map(|wanted| interfaces.iter().find(|i| i.name == *wanted).cloned())
+        .take(max)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices(totals: &[(&str, u64, u64)]) -> HashMap<OsString, (u64, u64)> {
+        totals
+            .iter()
+            .map(|&(name, read, written)| (name.into(), (read, written)))
+            .collect()
+    }
+
+    #[test]
+    fn disk_rates_per_device() {
+        let last = devices(&[("sda", 1000, 500), ("sdb", 4000, 4000)]);
+        let now = devices(&[("sda", 3000, 900), ("sdb", 4000, 6000)]);
+        assert_eq!(disk_rates(&now, &last, 2.0), (1000, 1200));
+        assert_eq!(disk_rates(&now, &last, 0.0), (0, 0));
+    }
+
+    #[test]
+    fn disks_coming_and_going_are_not_traffic() {
+        let last = devices(&[("sda", 1000, 1000), ("usb", 9000, 9000)]);
+        // The USB stick was pulled and another plugged in with large totals
+        let now = devices(&[("sda", 1100, 1000), ("sdc", 50_000, 70_000)]);
+        assert_eq!(disk_rates(&now, &last, 1.0), (100, 0));
+        // A device whose counters went backwards reads as idle
+        let now = devices(&[("sda", 10, 10)]);
+        assert_eq!(disk_rates(&now, &last, 1.0), (0, 0));
+    }
+}
@@ -1,7 +1,52 @@
-use crate::nostd_types::EventType;
+use crate::background::queue::{Coalesce, Priority};
+use crate::nostd_types::{
+    EventType, FOOTER, FrameHeader, HidEventImpl, Layout, MAX_HID_EVENT_SIZE,
+};
 
 pub trait HidEvent: Send + Sync {
     fn to_bytes(&self) -> Vec<u8>;
-    fn chunks(&self) -> Vec<Vec<u8>>;
     fn event_type(&self) -> EventType;
+    /// Hash of the event's image payload, for events that carry one. Lets the
+    /// HID handler skip images the device already has.
+    fn content_hash(&self) -> Option<u32> {
+        None
+    }
+    /// Which pending event this one replaces while queued, and which it
+    /// stands in for on reconnect. The default keeps only the newest event
+    /// of each type.
+    fn coalesce(&self) -> Coalesce {
+        Coalesce::Type
+    }
+    fn priority(&self) -> Priority {
+        Priority::of(self.event_type())
+    }
+    /// The payload for a device with `layout`. Events drawn at a size the
+    /// host works out (e.g. the CPU/RAM bar heights) override this.
+    fn to_bytes_for(&self, _layout: &Layout) -> Vec<u8> {
+        self.to_bytes()
+    }
+    /// The reports that make up this event for a device with `layout`,
+    /// framed with sequence id `seq`.
+    fn chunks(&self, seq: u8, layout: &Layout) -> Vec<HidEventImpl> {
+        frame(self.event_type(), seq, &self.to_bytes_for(layout))
+    }
+}
+
+/// Split `payload` into a header report, zero-padded payload reports and a
+/// footer report. Payloads longer than `u16::MAX` are truncated, since the
+/// header can't describe them; events that can get that big (images) must
+/// keep within it themselves.
+pub fn frame(event_type: EventType, seq: u8, payload: &[u8]) -> Vec<HidEventImpl> {
+    let payload = &payload[..payload.len().min(u16::MAX as usize)];
+    let mut reports = Vec::with_capacity(payload.len().div_ceil(MAX_HID_EVENT_SIZE) + 2);
+    reports.push(FrameHeader::new(event_type, seq, payload).to_report());
+    for c in payload.chunks(MAX_HID_EVENT_SIZE) {
+        let mut report = [0u8; MAX_HID_EVENT_SIZE];
+        report[..c.len()].copy_from_slice(c);
+        reports.push(report);
+    }
+    let mut footer = [0u8; MAX_HID_EVENT_SIZE];
+    footer[..FOOTER.len()].copy_from_slice(&FOOTER);
+    reports.push(footer);
+    reports
 }